@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use parser2::lexer::tokenize;
+
+fn identifiers() -> String {
+    let mut input = String::new();
+    for i in 0..2000 {
+        input.push_str(&format!(
+            "(define-public (transfer-{} (amount uint) (recipient principal)) (ok is-valid?))\n",
+            i
+        ));
+    }
+    input
+}
+
+fn strings() -> String {
+    let mut input = String::new();
+    for i in 0..2000 {
+        input.push_str(&format!(
+            "\"line {}\\n\\tquote \\\"this\\\" \\\\ ok\" u\"utf8 {} \\u{{1F600}}\\r\\n\"\n",
+            i, i
+        ));
+    }
+    input
+}
+
+fn buffers() -> String {
+    let mut input = String::new();
+    for _ in 0..2000 {
+        input.push_str("0x0123456789abcdeffedcba9876543210 0xdeadbeef\n");
+    }
+    input
+}
+
+fn nested() -> String {
+    let depth = 5000;
+    let mut input = String::new();
+    for i in 0..depth {
+        input.push_str(&format!("(+ {} ", i));
+    }
+    input.push_str(&")".repeat(depth));
+    input
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    for (name, input) in [
+        ("identifiers", identifiers()),
+        ("strings", strings()),
+        ("buffers", buffers()),
+        ("nested", nested()),
+    ] {
+        c.bench_function(name, |b| b.iter(|| tokenize(black_box(&input))));
+    }
+}
+
+criterion_group!(benches, bench_lexer);
+criterion_main!(benches);
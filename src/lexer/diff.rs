@@ -0,0 +1,123 @@
+// Compare two token streams, such as the tokens of a file before and after
+//  an edit.
+
+use super::token::PlacedToken;
+
+/// A difference between two token streams. Tokens are compared by value
+/// only, since an edit moves the spans of every token after it.
+#[derive(Debug, PartialEq)]
+pub enum TokenDiff<'a> {
+    /// A token in the new stream that is not in the old one.
+    Inserted(&'a PlacedToken),
+    /// A token in the old stream that is not in the new one.
+    Removed(&'a PlacedToken),
+    /// A token in the old stream replaced by a different one in the new.
+    Changed {
+        old: &'a PlacedToken,
+        new: &'a PlacedToken,
+    },
+}
+
+/// The smallest set of differences that turns `old` into `new`, in stream
+/// order. A removal directly followed by an insertion is reported as a
+/// change.
+pub fn diff_tokens<'a>(old: &'a [PlacedToken], new: &'a [PlacedToken]) -> Vec<TokenDiff<'a>> {
+    // Edits are usually small, so only the part between the common prefix
+    //  and suffix needs the full comparison
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| a.token == b.token)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.token == b.token)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    // `common[i][j]` is the length of the longest common subsequence of
+    //  `old[i..]` and `new[j..]`
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i].token == new[j].token {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diffs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].token == new[j].token {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diffs.push(TokenDiff::Removed(&old[i]));
+            i += 1;
+        } else {
+            match diffs.last() {
+                Some(TokenDiff::Removed(removed)) => {
+                    let removed = *removed;
+                    diffs.pop();
+                    diffs.push(TokenDiff::Changed {
+                        old: removed,
+                        new: &new[j],
+                    });
+                }
+                _ => diffs.push(TokenDiff::Inserted(&new[j])),
+            }
+            j += 1;
+        }
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Token;
+    use crate::lexer::tokenize;
+
+    #[test]
+    fn diff_inserted_token() {
+        let (old, _) = tokenize("(foo 1)");
+        let (new, _) = tokenize("(foo bar 1)");
+        let diffs = diff_tokens(&old, &new);
+        assert_eq!(diffs.len(), 2);
+        // The whitespace after `foo` is unchanged, so `bar` and the
+        //  whitespace after it are the inserted tokens
+        assert_eq!(
+            diffs,
+            vec![TokenDiff::Inserted(&new[3]), TokenDiff::Inserted(&new[4]),]
+        );
+        assert_eq!(new[3].token, Token::Ident("bar".to_string()));
+        assert_eq!(new[3].span.start_column, 6);
+
+        assert!(diff_tokens(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn diff_changed_token() {
+        let (old, _) = tokenize("(foo 1)");
+        let (new, _) = tokenize("(foo 2)");
+        assert_eq!(
+            diff_tokens(&old, &new),
+            vec![TokenDiff::Changed {
+                old: &old[3],
+                new: &new[3],
+            }]
+        );
+
+        let (new, _) = tokenize("(foo)");
+        assert_eq!(
+            diff_tokens(&old, &new),
+            vec![TokenDiff::Removed(&old[2]), TokenDiff::Removed(&old[3])]
+        );
+    }
+}
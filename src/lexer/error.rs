@@ -1,4 +1,7 @@
-#[derive(Debug, PartialEq)]
+use super::token::Position;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum LexerError {
     InvalidCharInt(char),
     InvalidCharUint(char),
@@ -10,4 +13,129 @@ pub enum LexerError {
     IllegalCharString(char),
     SingleSemiColon,
     UnknownSymbol(char),
+    KeywordShadow(String),
+    TokenTooLong,
+    IntegerOverflow,
+    InvalidCharLiteral,
+    UnexpectedNul,
+    LeadingZero,
+    MissingDigits,
+    InvalidAttribute,
+    MixedIndentation,
+    DanglingEscape,
+    NewlineInString,
+    GarbageRun,
+    MaxDepthExceeded,
+    InvalidUnicodeEscape,
+    IdentifierTooLong,
+    RawControlInString,
+    /// An operator which was likely a typo for the one given
+    LikelyTypo(String),
+    ScientificNotationUnsupported,
+    BufferTooLong,
+    MalformedNumber,
+}
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Severity {
+    /// The input is invalid, and its tokens are a best-effort recovery.
+    Error,
+    /// The input is valid as lexed, but likely not what was intended.
+    Warning,
+}
+
+impl LexerError {
+    /// How serious this diagnostic is. Warnings are reported where the
+    /// input is read the same as if nothing were wrong with it.
+    pub fn severity(&self) -> Severity {
+        match self {
+            LexerError::SingleSemiColon
+            | LexerError::KeywordShadow(_)
+            | LexerError::LeadingZero
+            | LexerError::MixedIndentation => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for LexerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexerError::InvalidCharInt(ch) => write!(f, "invalid character {ch:?} in integer"),
+            LexerError::InvalidCharUint(ch) => {
+                write!(f, "invalid character {ch:?} in unsigned integer")
+            }
+            LexerError::InvalidCharBuffer(ch) => write!(f, "invalid character {ch:?} in buffer"),
+            LexerError::InvalidCharIdent(ch) => {
+                write!(f, "invalid character {ch:?} in identifier")
+            }
+            LexerError::InvalidBufferLength(len) => {
+                write!(f, "buffer has an odd number of hex digits ({len})")
+            }
+            LexerError::UnknownEscapeChar(ch) => write!(f, "unknown escape \\{ch}"),
+            LexerError::UnterminatedString => write!(f, "unterminated string"),
+            LexerError::IllegalCharString(ch) => write!(f, "illegal character {ch:?} in string"),
+            LexerError::SingleSemiColon => write!(f, "comment starts with a single `;`"),
+            LexerError::UnknownSymbol(ch) => write!(f, "unknown symbol {ch:?}"),
+            LexerError::KeywordShadow(name) => write!(f, "identifier `{name}` shadows a keyword"),
+            LexerError::TokenTooLong => write!(f, "token is too long"),
+            LexerError::IntegerOverflow => write!(f, "integer is too large"),
+            LexerError::InvalidCharLiteral => write!(f, "invalid character literal"),
+            LexerError::UnexpectedNul => write!(f, "unexpected NUL character"),
+            LexerError::LeadingZero => write!(f, "integer has a leading zero"),
+            LexerError::MissingDigits => write!(f, "missing digits after radix prefix"),
+            LexerError::InvalidAttribute => write!(f, "invalid attribute"),
+            LexerError::MixedIndentation => write!(f, "indentation mixes tabs and spaces"),
+            LexerError::DanglingEscape => write!(f, "backslash with nothing to escape"),
+            LexerError::NewlineInString => write!(f, "line break in string"),
+            LexerError::GarbageRun => write!(f, "run of unrecognized characters"),
+            LexerError::MaxDepthExceeded => write!(f, "delimiters are nested too deeply"),
+            LexerError::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+            LexerError::IdentifierTooLong => write!(f, "identifier is too long"),
+            LexerError::RawControlInString => {
+                write!(f, "raw tab or line break in string must be escaped")
+            }
+            LexerError::LikelyTypo(intended) => write!(f, "did you mean `{intended}`?"),
+            LexerError::ScientificNotationUnsupported => {
+                write!(f, "scientific notation is not supported")
+            }
+            LexerError::BufferTooLong => write!(f, "buffer is too long"),
+            LexerError::MalformedNumber => write!(f, "malformed number"),
+        }
+    }
+}
+
+/// A position where lexing cannot start, because its line or column is 0.
+/// See `Lexer::new_at`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPosition(pub Position);
+
+impl std::fmt::Display for InvalidPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "lines and columns start at 1, but lexing was started at {}:{}",
+            self.0.line, self.0.column
+        )
+    }
+}
+
+impl std::error::Error for InvalidPosition {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_severity() {
+        assert_eq!(LexerError::UnknownSymbol('~').severity(), Severity::Error);
+        assert_eq!(LexerError::UnterminatedString.severity(), Severity::Error);
+        assert_eq!(LexerError::SingleSemiColon.severity(), Severity::Warning);
+        assert_eq!(
+            LexerError::KeywordShadow("true".to_string()).severity(),
+            Severity::Warning
+        );
+    }
 }
@@ -0,0 +1,205 @@
+// Conversion of spans to the positions used by the Language Server Protocol.
+
+use super::token::Span;
+
+/// The start of each line in a source, for looking up lines by number.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    // The byte offset at which each line starts
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// The text of the 1-based `line`, without its line ending. Lines past
+    /// the end of the source are empty.
+    pub fn line(&self, line: u32) -> &'a str {
+        let Some(&start) = self.line_starts.get(line.saturating_sub(1) as usize) else {
+            return "";
+        };
+        let end = self
+            .line_starts
+            .get(line as usize)
+            .map_or(self.source.len(), |next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    // Does the end of `line` reach the line ending, in a span ending at
+    //  `column`? Only lines followed by another have a line ending.
+    fn ends_at_line_break(&self, line: u32, column: u32) -> bool {
+        (line as usize) < self.line_starts.len()
+            && column as usize > self.line(line).chars().count()
+    }
+
+    // The UTF-16 offset into `line` after its first `chars` characters.
+    fn utf16_offset(&self, line: u32, chars: u32) -> u32 {
+        self.line(line)
+            .chars()
+            .take(chars as usize)
+            .map(|ch| ch.len_utf16() as u32)
+            .sum()
+    }
+}
+
+/// A position as the Language Server Protocol counts it: a 0-based line,
+/// and a 0-based offset into that line in UTF-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LspPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A range in the Language Server Protocol, whose end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LspRange {
+    pub start: LspPosition,
+    pub end: LspPosition,
+}
+
+impl Span {
+    /// This span as an LSP range in the source indexed by `line_index`.
+    /// Since a span's end is inclusive, the range ends after the span's
+    /// last character. A span ending with a line break ends at the start of
+    /// the next line. Other columns past the end of a line (e.g. for `Eof`)
+    /// are clamped to the end of the line, so a zero-width span stays empty.
+    /// The span's columns must count code points (`ColumnMode::Codepoints`).
+    pub fn to_lsp_range(&self, line_index: &LineIndex) -> LspRange {
+        let end = if line_index.ends_at_line_break(self.end_line, self.end_column) {
+            // The 1-based line is the 0-based number of the next line
+            LspPosition {
+                line: self.end_line,
+                character: 0,
+            }
+        } else {
+            LspPosition {
+                line: self.end_line.saturating_sub(1),
+                character: line_index.utf16_offset(self.end_line, self.end_column),
+            }
+        };
+        LspRange {
+            start: LspPosition {
+                line: self.start_line.saturating_sub(1),
+                character: line_index
+                    .utf16_offset(self.start_line, self.start_column.saturating_sub(1)),
+            },
+            end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Token;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn index_lines() {
+        let index = LineIndex::new("(a)\r\n\nb");
+        assert_eq!(index.line(1), "(a)");
+        assert_eq!(index.line(2), "");
+        assert_eq!(index.line(3), "b");
+        assert_eq!(index.line(4), "");
+    }
+
+    #[test]
+    fn convert_spans_to_lsp_ranges() {
+        // `😀` is one column, but two UTF-16 code units
+        let source = "(a u\"😀b\")\n  foo";
+        let index = LineIndex::new(source);
+        let mut lexer = Lexer::new(source);
+        lexer.read_token();
+        lexer.read_token();
+        lexer.read_token();
+        let string = lexer.read_token();
+        assert_eq!(string.token, Token::Utf8String("😀b".to_string()));
+        assert_eq!(
+            string.span.to_lsp_range(&index),
+            LspRange {
+                start: LspPosition {
+                    line: 0,
+                    character: 3
+                },
+                end: LspPosition {
+                    line: 0,
+                    character: 9
+                },
+            }
+        );
+
+        lexer.read_token();
+        lexer.read_token();
+        let ident = lexer.read_token();
+        assert_eq!(
+            ident.span.to_lsp_range(&index),
+            LspRange {
+                start: LspPosition {
+                    line: 1,
+                    character: 2
+                },
+                end: LspPosition {
+                    line: 1,
+                    character: 5
+                },
+            }
+        );
+
+        let eof = lexer.read_token();
+        assert_eq!(eof.token, Token::Eof);
+        let range = eof.span.to_lsp_range(&index);
+        assert_eq!(range.start, range.end);
+        assert_eq!(range.start.character, 5);
+    }
+
+    #[test]
+    fn end_lsp_ranges_at_line_breaks() {
+        let source = "a \nb";
+        let index = LineIndex::new(source);
+        let mut lexer = Lexer::new(source);
+        lexer.read_token();
+        let whitespace = lexer.read_token();
+        assert_eq!(whitespace.token, Token::Whitespace);
+        assert_eq!(
+            whitespace.span.to_lsp_range(&index),
+            LspRange {
+                start: LspPosition {
+                    line: 0,
+                    character: 1
+                },
+                end: LspPosition {
+                    line: 1,
+                    character: 0
+                },
+            }
+        );
+
+        // The same for a significant newline, and a `\r\n` line ending
+        let source = "a\r\nb";
+        let index = LineIndex::new(source);
+        let mut lexer = Lexer::builder().significant_newlines(true).build(source);
+        lexer.read_token();
+        let newline = lexer.read_token();
+        assert_eq!(newline.token, Token::Newline);
+        assert_eq!(
+            newline.span.to_lsp_range(&index),
+            LspRange {
+                start: LspPosition {
+                    line: 0,
+                    character: 1
+                },
+                end: LspPosition {
+                    line: 1,
+                    character: 0
+                },
+            }
+        );
+    }
+}
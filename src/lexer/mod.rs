@@ -1,21 +1,81 @@
+pub mod diff;
 pub mod error;
+pub mod line_index;
+pub mod options;
+pub mod source;
+pub mod stream;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod token;
+#[cfg(feature = "trace")]
+pub mod trace;
 
-use std::{char, str::Chars};
-use token::{PlacedToken, Span, Token};
+use line_index::LineIndex;
+use std::{
+    borrow::Cow,
+    char,
+    collections::{HashSet, VecDeque},
+};
+use token::{FileId, FileSpan, PlacedToken, Position, Span, Token, TokenCategory, KEYWORDS};
 
-use crate::lexer::error::LexerError;
+use crate::lexer::error::{InvalidPosition, LexerError};
+use crate::lexer::options::{EscapeErrorPolicy, LeadingZeros, LexerBuilder, LexerOptions};
+use crate::lexer::source::CharSource;
+#[cfg(feature = "trace")]
+use crate::lexer::trace::TraceEvent;
+
+type ErrorObserver<'a> = Box<dyn FnMut(&PlacedToken) + 'a>;
 
 pub struct Lexer<'a> {
-    input: Chars<'a>,
+    input: Box<dyn CharSource + 'a>,
+    // The input as a string, when it was given as one, so that identifiers
+    //  can be borrowed from it rather than copied
+    source: Option<&'a str>,
+    // Characters read from `input` by `peek_char` but not yet consumed
+    lookahead: VecDeque<char>,
     next: char,
+    // Has the input been exhausted? `next` is `'\0'` at the end of the
+    //  input, but a NUL character may also appear in the input itself.
+    eof: bool,
     offset: usize,
+    // The position of `next` in bytes
+    byte_offset: usize,
     pub line: usize,
     pub column: usize,
     pub last_line: usize,
     pub last_column: usize,
     // While lexing, collect errors and continue on (when possible)
     errors: Vec<PlacedToken>,
+    // Advisory diagnostics, which do not indicate a problem with the input
+    warnings: Vec<PlacedToken>,
+    // Tokens to return before reading any more, for `inline_errors`
+    queued: VecDeque<PlacedToken>,
+    // A token read ahead to end a run of unknown symbols, for
+    //  `collapse_errors`, along with the errors reported with it
+    pending: Option<(PlacedToken, Vec<PlacedToken>)>,
+    options: LexerOptions,
+    file_id: FileId,
+    // How many `(` and `{` enclose the next token
+    depth: usize,
+    // Openers past `max_delimiter_depth`, which are not counted in `depth`
+    excess_depth: usize,
+    // Did the last token, other than whitespace and comments, close a
+    //  top-level form?
+    closed_form: bool,
+    // How many characters error recovery skipped, the last time and in total
+    last_recovery_len: usize,
+    total_recovery_len: usize,
+    // Was the last string or buffer read longer than the configured limit?
+    //  It is reported with the token, by `check_length`.
+    truncated: bool,
+    // Called with each error once the token it was reported with is read
+    on_error: Option<ErrorObserver<'a>>,
+    // The grapheme cluster which `next` is part of, for
+    //  `ColumnMode::Graphemes`
+    #[cfg(feature = "graphemes")]
+    grapheme: String,
+    #[cfg(feature = "trace")]
+    trace: Vec<TraceEvent>,
 }
 
 fn is_separator(ch: char) -> bool {
@@ -25,22 +85,466 @@ fn is_separator(ch: char) -> bool {
     }
 }
 
+// The error to report for two adjacent errors, if they can be reported as
+//  one: identical errors are kept, while other errors of the same kind, or
+//  runs of unknown symbols, become a `GarbageRun`.
+fn merge_errors(a: &LexerError, b: &LexerError) -> Option<LexerError> {
+    let garbage =
+        |e: &LexerError| matches!(e, LexerError::UnknownSymbol(_) | LexerError::GarbageRun);
+    if a == b {
+        Some(a.clone())
+    } else if std::mem::discriminant(a) == std::mem::discriminant(b) || (garbage(a) && garbage(b)) {
+        Some(LexerError::GarbageRun)
+    } else {
+        None
+    }
+}
+
+// Truncate `s` to at most `max` bytes (on a character boundary), returning
+//  whether it was too long.
+fn truncate_str(s: &mut String, max: Option<usize>) -> bool {
+    match max {
+        Some(max) if s.len() > max => {
+            let mut end = max;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            s.truncate(end);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Lex all of `input`, returning the tokens (ending with `Eof`) and the
+/// errors reported along the way.
+pub fn tokenize(input: &str) -> (Vec<PlacedToken>, Vec<PlacedToken>) {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = vec![];
+    loop {
+        let token = lexer.read_token();
+        let eof = token.token == Token::Eof;
+        tokens.push(token);
+        if eof {
+            return (tokens, lexer.errors);
+        }
+    }
+}
+
+/// Lex all of `input` into a stable textual form, one token per line with
+/// its span, followed by any errors in the same form. Useful for snapshot
+/// tests.
+pub fn debug_dump(input: &str) -> String {
+    let (tokens, errors) = tokenize(input);
+    let mut dump = String::new();
+    for placed in tokens.iter().chain(errors.iter()) {
+        dump.push_str(&format!(
+            "{}:{}..{}:{}: {:?}\n",
+            placed.span.start_line,
+            placed.span.start_column,
+            placed.span.end_line,
+            placed.span.end_column,
+            placed.token
+        ));
+    }
+    dump
+}
+
+/// Write `tokens` back out as source text, the inverse of lexing. Each
+/// token is written as its `Display` form, so lexing the result gives the
+/// same tokens, but not necessarily the original text: runs of whitespace
+/// are collapsed to a single space or line break, number and buffer literals
+/// are written in a canonical form, comments are written with `;; `, and
+/// unknown symbols are dropped.
+pub fn render(tokens: &[PlacedToken]) -> String {
+    let mut rendered = String::new();
+    let mut after_comment = false;
+    for (i, placed) in tokens.iter().enumerate() {
+        match placed.token {
+            // Whitespace which ends a line is written as a line break. A
+            //  comment runs to the end of its line, so whitespace after one
+            //  must include the line break.
+            Token::Whitespace
+                if after_comment
+                    || tokens
+                        .get(i + 1)
+                        .is_some_and(|next| next.span.start_line > placed.span.start_line) =>
+            {
+                rendered.push('\n')
+            }
+            _ => rendered.push_str(&placed.token.to_string()),
+        }
+        after_comment = matches!(placed.token, Token::Comment(_));
+    }
+    rendered
+}
+
+/// Render `err`, an error reported while lexing `source`, as a short
+/// human-readable report: the message, the line of source it is on, and
+/// carets under the span. A span covering several lines is only underlined
+/// on its first line.
+pub fn format_error(source: &str, err: &PlacedToken) -> String {
+    let message = match &err.token {
+        Token::Error(error) => error.to_string(),
+        token => format!("{token:?}"),
+    };
+    let span = &err.span;
+    let line = LineIndex::new(source).line(span.start_line);
+    let end_column = if span.end_line == span.start_line {
+        span.end_column
+    } else {
+        line.chars().count() as u32
+    };
+
+    // Line the carets up with the span, keeping any tabs in the source
+    let mut underline: String = line
+        .chars()
+        .take(span.start_column.saturating_sub(1) as usize)
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = end_column.saturating_sub(span.start_column) + 1;
+    underline.push_str(&"^".repeat(width as usize));
+
+    let number = span.start_line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "error: {message}\n{gutter}--> {}:{}\n{gutter} |\n{number} | {line}\n{gutter} | {underline}\n",
+        span.start_line, span.start_column
+    )
+}
+
+/// Split `input` into its top-level forms, for tools which process one
+/// definition at a time. Each form is a balanced `(...)` (or `{...}`) along
+/// with any comments directly before it, paired with the span covering them.
+/// A token outside of any form, such as a stray `)`, is a group of its own,
+/// as are any comments after the last form. A form which is still open at
+/// the end of the input runs to its last token. Whitespace between groups is
+/// dropped, and errors are ignored.
+pub fn top_level_forms(input: &str) -> Vec<(Span, Vec<PlacedToken>)> {
+    fn finish(forms: &mut Vec<(Span, Vec<PlacedToken>)>, tokens: Vec<PlacedToken>) {
+        if let (Some(first), Some(last)) = (tokens.first(), tokens.last()) {
+            forms.push((first.span.cover(&last.span), tokens));
+        }
+    }
+
+    let mut lexer = Lexer::new(input);
+    let mut forms = vec![];
+    let mut current: Vec<PlacedToken> = vec![];
+    let mut depth = 0;
+    loop {
+        let placed = lexer.read_token();
+        if depth == 0 {
+            match placed.token {
+                Token::Eof => break,
+                // Whitespace is only kept between comments and their form
+                Token::Whitespace if current.is_empty() => continue,
+                Token::Whitespace | Token::Comment(_) => {
+                    current.push(placed);
+                    continue;
+                }
+                Token::Lparen | Token::Lbrace => (),
+                _ => {
+                    current.push(placed);
+                    finish(&mut forms, std::mem::take(&mut current));
+                    continue;
+                }
+            }
+        }
+        match placed.token {
+            Token::Eof => break,
+            Token::Lparen | Token::Lbrace => depth += 1,
+            Token::Rparen | Token::Rbrace => depth -= 1,
+            _ => (),
+        }
+        current.push(placed);
+        if depth == 0 {
+            finish(&mut forms, std::mem::take(&mut current));
+        }
+    }
+
+    while matches!(current.last(), Some(placed) if matches!(placed.token, Token::Whitespace)) {
+        current.pop();
+    }
+    finish(&mut forms, current);
+    forms
+}
+
+/// Lex `input`, returning only the comments. A comment starting with a
+/// single `;` is reported as an error by the lexer, but still returned.
+pub fn comments(input: &str) -> Vec<PlacedToken> {
+    let mut lexer = Lexer::new(input);
+    let mut comments = vec![];
+    loop {
+        let token = lexer.read_token();
+        match token.token {
+            Token::Eof => return comments,
+            Token::Comment(_) => comments.push(token),
+            _ => (),
+        }
+    }
+}
+
+/// Remove the comments from `input`, returning what is left along with the
+/// comments removed. Only the comment itself is removed: the whitespace
+/// around it, including the line ending after it, is kept, so every other
+/// token stays on the same line.
+pub fn strip_comments(input: &str) -> (String, Vec<PlacedToken>) {
+    let mut lexer = Lexer::new(input);
+    let mut stripped = String::with_capacity(input.len());
+    let mut comments = vec![];
+    let mut kept = 0;
+    loop {
+        let start = lexer.byte_offset;
+        let token = lexer.read_token();
+        match token.token {
+            Token::Eof => break,
+            Token::Comment(_) => {
+                stripped.push_str(&input[kept..start]);
+                kept = lexer.byte_offset;
+                comments.push(token);
+            }
+            _ => (),
+        }
+    }
+    stripped.push_str(&input[kept..]);
+    (stripped, comments)
+}
+
+/// Tidy the whitespace between tokens in `input`: runs of spaces and tabs
+/// within a line are collapsed to a single space, and whitespace at the end
+/// of each line is removed. Indentation at the start of a line is kept as it
+/// is, as are line endings and the contents of strings and comments.
+pub fn normalize_whitespace(input: &str) -> String {
+    let mut lexer = Lexer::new(input);
+    let mut normalized = String::with_capacity(input.len());
+    loop {
+        let start = lexer.byte_offset;
+        let token = lexer.read_token();
+        let text = &input[start..lexer.byte_offset.min(input.len())];
+        match token.token {
+            Token::Eof => return normalized,
+            Token::Whitespace => {
+                let mut lines = text.split('\n').peekable();
+                let mut first = true;
+                while let Some(line) = lines.next() {
+                    if lines.peek().is_some() {
+                        // Keep the line ending, but not the whitespace before it
+                        if line.ends_with('\r') {
+                            normalized.push('\r');
+                        }
+                        normalized.push('\n');
+                    } else if lexer.at_eof() {
+                        // Trailing whitespace at the end of the input
+                    } else if first && start > 0 {
+                        normalized.push(' ');
+                    } else {
+                        normalized.push_str(line);
+                    }
+                    first = false;
+                }
+            }
+            _ => normalized.push_str(text),
+        }
+    }
+}
+
+/// The runs of two or more consecutive blank lines (lines with nothing but
+/// whitespace) in `input`. Each span runs from the start of the first blank
+/// line in the run to the end of the last, including its line ending.
+pub fn blank_line_runs(input: &str) -> Vec<Span> {
+    let mut lexer = Lexer::new(input);
+    // Each blank line, along with the column where it ends
+    let mut blank_lines: Vec<(u32, u32)> = vec![];
+    loop {
+        let start = lexer.byte_offset;
+        let placed = lexer.read_token();
+        match placed.token {
+            Token::Eof => break,
+            Token::Whitespace => {
+                let text = &input[start..lexer.byte_offset.min(input.len())];
+                let lines: Vec<&str> = text.split('\n').collect();
+                for (i, line) in lines.iter().enumerate() {
+                    let last = i == lines.len() - 1;
+                    // The first line only counts if this whitespace starts
+                    //  it, and the last only counts if nothing follows it
+                    let whole_line = (i > 0 || placed.span.start_column == 1)
+                        && (!last || (lexer.at_eof() && !line.is_empty()));
+                    if whole_line {
+                        let length = line.chars().count() as u32;
+                        let end_column = if last { length } else { length + 1 };
+                        blank_lines.push((placed.span.start_line + i as u32, end_column));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut runs: Vec<Span> = vec![];
+    let mut run_length = 0;
+    for (i, &(line, end_column)) in blank_lines.iter().enumerate() {
+        let continues = i > 0 && blank_lines[i - 1].0 + 1 == line;
+        run_length = if continues { run_length + 1 } else { 1 };
+        if run_length == 2 {
+            runs.push(Span {
+                start_line: line - 1,
+                start_column: 1,
+                end_line: line,
+                end_column,
+            });
+        } else if run_length > 2 {
+            let run = runs.last_mut().unwrap();
+            run.end_line = line;
+            run.end_column = end_column;
+        }
+    }
+    runs
+}
+
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_options(input, LexerOptions::default())
+    }
+
+    pub fn builder() -> LexerBuilder {
+        LexerBuilder::new()
+    }
+
+    /// Create a lexer for `input`, which is the contents of the file
+    /// identified by `file_id`.
+    pub fn new_in_file(input: &'a str, file_id: FileId) -> Self {
+        LexerBuilder::new().file_id(file_id).build(input)
+    }
+
+    pub fn with_options(input: &'a str, options: LexerOptions) -> Self {
+        let mut lexer = Self::with_source(input.chars(), options);
+        lexer.source = Some(input);
+        lexer
+    }
+
+    /// Create a lexer for `input`, which is part of a larger file starting
+    /// at `start`, so that spans are reported in the coordinates of that
+    /// file. This fails if `start` is not a valid position, i.e. its line or
+    /// column is 0, or if the spans of `input` would not fit from there.
+    pub fn new_at(input: &'a str, start: Position) -> Result<Self, InvalidPosition> {
+        Self::new_at_with_options(input, start, LexerOptions::default())
+    }
+
+    /// Like `new_at`, with the given options.
+    pub fn new_at_with_options(
+        input: &'a str,
+        start: Position,
+        options: LexerOptions,
+    ) -> Result<Self, InvalidPosition> {
+        if start.line == 0 || start.column == 0 {
+            return Err(InvalidPosition(start));
+        }
+        // The position just past the end of `input`, where `Eof` is placed
+        let lines = input.matches('\n').count() as u64;
+        let first_line = input.split('\n').next().unwrap_or("").chars().count() as u64;
+        let max = u64::from(u32::MAX);
+        if u64::from(start.line) + lines > max || u64::from(start.column) + first_line > max {
+            return Err(InvalidPosition(start));
+        }
+        let mut lexer = Self::with_options(input, options);
+        lexer.line = start.line as usize;
+        lexer.column = start.column as usize;
+        Ok(lexer)
+    }
+
+    pub fn with_source(input: impl CharSource + 'a, options: LexerOptions) -> Self {
         let mut s = Self {
-            input: input.chars(),
+            input: Box::new(input),
+            source: None,
+            lookahead: VecDeque::new(),
             next: 0 as char,
+            eof: false,
             offset: 0,
+            byte_offset: 0,
             line: 1,
             column: 0,
             last_line: 0,
             last_column: 0,
             errors: vec![],
+            warnings: vec![],
+            queued: VecDeque::new(),
+            pending: None,
+            options,
+            file_id: FileId::default(),
+            depth: 0,
+            excess_depth: 0,
+            closed_form: false,
+            last_recovery_len: 0,
+            total_recovery_len: 0,
+            truncated: false,
+            on_error: None,
+            #[cfg(feature = "graphemes")]
+            grapheme: String::new(),
+            #[cfg(feature = "trace")]
+            trace: vec![],
         };
         s.read_char(); // Initialize with the first character
         s
     }
 
+    /// The input which has not been lexed yet, starting at the next
+    /// character. This is empty if the lexer was not created from a `&str`.
+    pub fn remaining(&self) -> &'a str {
+        match self.source {
+            Some(source) => &source[self.byte_offset.min(source.len())..],
+            None => "",
+        }
+    }
+
+    pub fn file_id(&self) -> FileId {
+        self.file_id
+    }
+
+    /// Attach the file being lexed to `span`.
+    pub fn file_span(&self, span: &Span) -> FileSpan {
+        span.clone().in_file(self.file_id)
+    }
+
+    /// Every character read and token returned so far, in order.
+    #[cfg(feature = "trace")]
+    pub fn trace(&self) -> &[TraceEvent] {
+        &self.trace
+    }
+
+    /// The errors reported so far, in the order they were discovered.
+    pub fn errors(&self) -> &[PlacedToken] {
+        &self.errors
+    }
+
+    pub fn had_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// The errors reported so far, ordered by where they start in the input
+    /// (errors are recorded in the order they are discovered, which can
+    /// differ). Identical errors at the same span are only included once.
+    pub fn sorted_errors(&self) -> Vec<&PlacedToken> {
+        let mut errors: Vec<&PlacedToken> = self.errors.iter().collect();
+        errors.sort_by_key(|e| {
+            (
+                e.span.start_line,
+                e.span.start_column,
+                e.span.end_line,
+                e.span.end_column,
+            )
+        });
+        errors.dedup_by(|a, b| a.span == b.span && a.token == b.token);
+        errors
+    }
+
+    pub fn warnings(&self) -> &[PlacedToken] {
+        &self.warnings
+    }
+
     pub fn read_char(&mut self) {
         self.last_line = self.line;
         self.last_column = self.column;
@@ -49,25 +553,118 @@ impl<'a> Lexer<'a> {
             self.line = self.line + 1;
             self.column = 0;
         }
+        if self.offset > 0 {
+            self.byte_offset += self.next.len_utf8();
+        }
 
-        match self.input.next() {
+        match self
+            .lookahead
+            .pop_front()
+            .or_else(|| self.input.next_char())
+        {
             Some(ch) => self.next = ch,
-            None => self.next = '\0',
+            None => {
+                self.next = '\0';
+                self.eof = true;
+            }
         }
         self.offset = self.offset + 1;
-        self.column = self.column + 1;
+        if !self.continues_grapheme() {
+            self.column += 1;
+        }
+
+        #[cfg(feature = "trace")]
+        self.trace.push(TraceEvent::ReadChar {
+            ch: self.next,
+            line: self.line as u32,
+            column: self.column as u32,
+        });
+    }
+
+    // Does `next` continue the grapheme cluster of the characters before
+    //  it? Always false unless columns count grapheme clusters.
+    fn continues_grapheme(&mut self) -> bool {
+        #[cfg(feature = "graphemes")]
+        if self.options.column_mode == options::ColumnMode::Graphemes {
+            use unicode_segmentation::UnicodeSegmentation;
+
+            // A line ending starts a new column even after a `\r`, as it
+            //  does when counting code points
+            let starts_cluster = self.grapheme.is_empty() || self.next == '\n' || self.at_eof();
+            self.grapheme.push(self.next);
+            if !starts_cluster && self.grapheme.graphemes(true).count() == 1 {
+                return true;
+            }
+            self.grapheme.clear();
+            self.grapheme.push(self.next);
+        }
+        false
+    }
+
+    // Look past `next` without consuming anything: `peek_char(0)` is the
+    //  character that will follow `next`.
+    fn peek_char(&mut self, n: usize) -> char {
+        while self.lookahead.len() <= n {
+            match self.input.next_char() {
+                Some(ch) => self.lookahead.push_back(ch),
+                None => return '\0',
+            }
+        }
+        self.lookahead[n]
+    }
+
+    // Is `next` the end of the input, rather than a NUL in the input?
+    fn at_eof(&self) -> bool {
+        self.next == '\0' && self.eof
     }
 
     pub fn skip_whitespace(&mut self) {
-        while self.next != '\0' {
+        self.read_whitespace();
+    }
+
+    // Skip a run of whitespace, returning whether it is indentation: it
+    //  reaches the start of a line, and is not at the end of the input.
+    fn read_whitespace(&mut self) -> bool {
+        // Where the indentation of the current line starts, if this
+        //  whitespace includes the start of a line
+        let mut indent = (self.column == 1).then_some((self.line, self.column));
+        let (mut tabs, mut spaces) = (false, false);
+        while matches!(self.next, ' ' | '\t' | '\r' | '\n') {
+            if self.options.significant_newlines && self.at_line_end() {
+                break;
+            }
             match self.next {
-                ' ' | '\t' | '\r' | '\n' => (),
-                _ => break,
+                '\n' => {
+                    indent = Some((self.line + 1, 1));
+                    (tabs, spaces) = (false, false);
+                }
+                '\t' => tabs = true,
+                ' ' => spaces = true,
+                _ => (),
             }
             self.read_char();
         }
+
+        if let Some((line, column)) = indent {
+            if self.options.warn_mixed_indentation && tabs && spaces && !self.at_eof() {
+                self.warnings.push(PlacedToken {
+                    span: Span {
+                        start_line: line as u32,
+                        start_column: column as u32,
+                        end_line: self.last_line as u32,
+                        end_column: self.last_column as u32,
+                    },
+                    token: Token::Error(LexerError::MixedIndentation),
+                });
+            }
+        }
+
+        indent.is_some() && !self.at_eof()
     }
 
+    /// Read the rest of the current line. The line ending (`\n` or `\r\n`)
+    /// is not consumed, so a comment's span ends at its last character and
+    /// the line ending begins the following whitespace token.
     pub fn read_line(&mut self) -> String {
         let mut line = String::new();
         loop {
@@ -75,8 +672,17 @@ impl<'a> Lexer<'a> {
                 '\n' => {
                     break;
                 }
-                '\0' => break,
-                '\r' => (),
+                '\0' if self.at_eof() => break,
+                '\0' => {
+                    self.report_nul();
+                    line.push('\0');
+                }
+                // Leave a `\r\n` line ending unconsumed, like a `\n`
+                '\r' => {
+                    if self.peek_char(0) == '\n' {
+                        break;
+                    }
+                }
                 ch => line.push(ch),
             }
             self.read_char();
@@ -87,9 +693,12 @@ impl<'a> Lexer<'a> {
     fn proceed_through_error(&mut self, err: LexerError) {
         let start_line = self.line as u32;
         let start_column = self.column as u32;
+        let start = self.offset;
         while !is_separator(self.next) {
             self.read_char();
         }
+        self.last_recovery_len = self.offset - start;
+        self.total_recovery_len += self.last_recovery_len;
         self.errors.push(PlacedToken {
             span: Span {
                 start_line,
@@ -101,61 +710,312 @@ impl<'a> Lexer<'a> {
         });
     }
 
-    pub fn read_identifier(&mut self, first: Option<char>) -> String {
+    // Recover from `err`, found at `next` after the digits of a decimal
+    //  integer which starts at `start_line`:`start_column`. When the digits
+    //  are followed by an exponent, like `1e10`, or a decimal point, like
+    //  `1.2.3`, the whole run is reported as `ScientificNotationUnsupported`
+    //  or `MalformedNumber` instead.
+    fn proceed_through_number_error(
+        &mut self,
+        start_line: u32,
+        start_column: u32,
+        err: LexerError,
+    ) {
+        let digit_follows = self.peek_char(0).is_ascii_digit();
+        let error = match self.next {
+            'e' | 'E' if digit_follows => LexerError::ScientificNotationUnsupported,
+            '.' if digit_follows => LexerError::MalformedNumber,
+            _ => {
+                self.proceed_through_error(err);
+                return;
+            }
+        };
+        self.proceed_through_error(error);
+        if let Some(error) = self.errors.last_mut() {
+            error.span.start_line = start_line;
+            error.span.start_column = start_column;
+        }
+    }
+
+    // Is `next` the first of a run of signs directly followed by a digit,
+    //  like `+-5`?
+    fn starts_malformed_sign(&mut self) -> bool {
+        if !matches!(self.next, '+' | '-') {
+            return false;
+        }
+        let mut n = 0;
+        while matches!(self.peek_char(n), '+' | '-') {
+            n += 1;
+        }
+        n > 0 && self.peek_char(n).is_ascii_digit()
+    }
+
+    /// Read an identifier, following `first` if it has already been
+    /// consumed. The identifier is borrowed from the input when the lexer was
+    /// created from a `&str`, and copied otherwise.
+    pub fn read_identifier(&mut self, first: Option<char>) -> Cow<'a, str> {
+        let source = self.source;
+        let start = self.byte_offset - first.map_or(0, char::len_utf8);
         let mut ident = String::new();
-        if let Some(first) = first {
-            ident.push(first);
+        if source.is_none() {
+            ident.extend(first);
         }
 
-        loop {
+        let end = loop {
             match self.next {
-                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '!' | '?' => ident.push(self.next),
-                _ => {
-                    if is_separator(self.next) {
-                        return ident;
-                    } else {
-                        self.proceed_through_error(LexerError::InvalidCharIdent(self.next));
-                        return ident;
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '!' | '?' => {
+                    if source.is_none() {
+                        ident.push(self.next);
                     }
                 }
+                _ => break self.byte_offset,
             }
             self.read_char();
+        };
+        // A `.` ends an identifier, for field access like `a.b`
+        if !is_separator(self.next) && self.next != '.' {
+            self.proceed_through_error(LexerError::InvalidCharIdent(self.next));
+        }
+
+        match source {
+            Some(source) => Cow::Borrowed(&source[start..end]),
+            None => Cow::Owned(ident),
+        }
+    }
+
+    // Read an identifier, or with `qualified_names`, a dotted name like
+    //  `contract.function` which starts with one.
+    fn read_name(&mut self, first: Option<char>) -> Token {
+        let ident = self.read_identifier(first);
+        if !self.options.qualified_names
+            || self.next != '.'
+            || !self.peek_char(0).is_ascii_alphabetic()
+        {
+            return Token::Ident(ident.into_owned());
+        }
+        let mut name = ident.into_owned();
+        self.read_qualified_segments(&mut name);
+        Token::QualifiedName(name)
+    }
+
+    // Append each `.name` which follows directly to `name`.
+    fn read_qualified_segments(&mut self, name: &mut String) {
+        while self.next == '.' && self.peek_char(0).is_ascii_alphabetic() {
+            self.read_char();
+            name.push('.');
+            name.push_str(&self.read_identifier(None));
         }
     }
 
     pub fn read_unsigned(&mut self) -> u128 {
+        let start_line = self.line as u32;
+        let start_column = self.column as u32;
         let mut num: u128 = 0;
+        let mut overflow = false;
         while self.next.is_ascii_digit() {
             let digit = self.next as u32 - '0' as u32;
-            num = num * 10 + digit as u128;
+            match num
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(digit as u128))
+            {
+                Some(n) => num = n,
+                None => {
+                    overflow = true;
+                    num = u128::MAX;
+                }
+            }
             self.read_char();
         }
+        if overflow {
+            self.errors.push(PlacedToken {
+                span: Span {
+                    start_line,
+                    start_column,
+                    end_line: self.last_line as u32,
+                    end_column: self.last_column as u32,
+                },
+                token: Token::Error(LexerError::IntegerOverflow),
+            });
+        }
         if !is_separator(self.next) {
-            self.proceed_through_error(LexerError::InvalidCharUint(self.next));
+            self.proceed_through_number_error(
+                start_line,
+                start_column,
+                LexerError::InvalidCharUint(self.next),
+            );
         }
         num
     }
 
     pub fn read_integer(&mut self) -> i128 {
+        let start_line = self.line as u32;
+        let start_column = self.column as u32;
         let mut num: i128 = 0;
+        let mut overflow = false;
         while self.next.is_ascii_digit() {
             let digit = self.next as u32 - '0' as u32;
-            num = num * 10 + digit as i128;
+            match num
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(digit as i128))
+            {
+                Some(n) => num = n,
+                None => {
+                    overflow = true;
+                    num = i128::MAX;
+                }
+            }
+            self.read_char();
+        }
+        if overflow {
+            self.errors.push(PlacedToken {
+                span: Span {
+                    start_line,
+                    start_column,
+                    end_line: self.last_line as u32,
+                    end_column: self.last_column as u32,
+                },
+                token: Token::Error(LexerError::IntegerOverflow),
+            });
+        }
+        if !is_separator(self.next) {
+            self.proceed_through_number_error(
+                start_line,
+                start_column,
+                LexerError::InvalidCharInt(self.next),
+            );
+        }
+        num
+    }
+
+    // Read an unsigned integer literal, as a `BigUint` if it does not fit in
+    //  a `u128` and big integers are enabled.
+    fn read_uint_token(&mut self) -> Token {
+        #[cfg(feature = "bignum")]
+        if self.options.big_integers {
+            let (start_line, start_column) = (self.line as u32, self.column as u32);
+            let digits = self.read_digits();
+            if !is_separator(self.next) {
+                self.proceed_through_number_error(
+                    start_line,
+                    start_column,
+                    LexerError::InvalidCharUint(self.next),
+                );
+            }
+            return match digits.parse() {
+                Ok(num) => Token::Uint(num),
+                Err(_) => Token::BigUint(digits.parse().unwrap()),
+            };
+        }
+        Token::Uint(self.read_unsigned())
+    }
+
+    // Read a signed integer literal, as a `BigInt` if it does not fit in an
+    //  `i128` and big integers are enabled.
+    fn read_int_token(&mut self) -> Token {
+        #[cfg(feature = "bignum")]
+        if self.options.big_integers {
+            let (start_line, start_column) = (self.line as u32, self.column as u32);
+            let digits = self.read_digits();
+            if !is_separator(self.next) {
+                self.proceed_through_number_error(
+                    start_line,
+                    start_column,
+                    LexerError::InvalidCharInt(self.next),
+                );
+            }
+            return match digits.parse() {
+                Ok(num) => Token::Int(num),
+                Err(_) => Token::BigInt(digits.parse().unwrap()),
+            };
+        }
+        Token::Int(self.read_integer())
+    }
+
+    #[cfg(feature = "bignum")]
+    fn read_digits(&mut self) -> String {
+        let mut digits = String::new();
+        while self.next.is_ascii_digit() {
+            digits.push(self.next);
+            self.read_char();
+        }
+        digits
+    }
+
+    // Read the digits of an integer literal in `radix` (at most 10), up to
+    //  a maximum value of `max`.
+    fn read_radix(&mut self, radix: u32, max: u128) -> u128 {
+        let start_line = self.line as u32;
+        let start_column = self.column as u32;
+        let mut num: u128 = 0;
+        let mut overflow = false;
+        while let Some(digit) = self.next.to_digit(radix) {
+            match num
+                .checked_mul(radix as u128)
+                .and_then(|n| n.checked_add(digit as u128))
+                .filter(|n| *n <= max)
+            {
+                Some(n) => num = n,
+                None => {
+                    overflow = true;
+                    num = max;
+                }
+            }
             self.read_char();
         }
+        if overflow {
+            self.errors.push(PlacedToken {
+                span: Span {
+                    start_line,
+                    start_column,
+                    end_line: self.last_line as u32,
+                    end_column: self.last_column as u32,
+                },
+                token: Token::Error(LexerError::IntegerOverflow),
+            });
+        }
         if !is_separator(self.next) {
             self.proceed_through_error(LexerError::InvalidCharInt(self.next));
         }
         num
     }
 
+    // Read an integer literal with a radix prefix like `0b`, starting at the
+    //  prefix letter (the `0` has already been consumed).
+    fn read_prefixed(&mut self, radix: u32, max: u128) -> u128 {
+        let start_line = self.last_line as u32;
+        let start_column = self.last_column as u32;
+        self.read_char();
+        if self.next.to_digit(radix).is_none() {
+            if is_separator(self.next) {
+                self.errors.push(PlacedToken {
+                    span: Span {
+                        start_line,
+                        start_column,
+                        end_line: self.last_line as u32,
+                        end_column: self.last_column as u32,
+                    },
+                    token: Token::Error(LexerError::MissingDigits),
+                });
+            } else {
+                self.proceed_through_error(LexerError::InvalidCharInt(self.next));
+            }
+            return 0;
+        }
+        self.read_radix(radix, max)
+    }
+
     pub fn read_hex(&mut self) -> Vec<u8> {
         let start_line = self.line as u32;
         let start_column = (self.column - 1) as u32;
         let mut bytes = vec![];
+        self.truncated = false;
         loop {
             self.read_char();
 
+            if self.options.hex_whitespace && matches!(self.next, ' ' | '\t') {
+                self.skip_hex_whitespace();
+            }
+
             let f = self.next;
             if !f.is_ascii_hexdigit() {
                 if !is_separator(f) {
@@ -183,48 +1043,148 @@ impl<'a> Lexer<'a> {
                 return bytes;
             }
 
-            bytes.push((f.to_digit(16).unwrap() * 0x10 + s.to_digit(16).unwrap()) as u8);
+            // Past the limit, the rest of the buffer is read but not stored
+            if self
+                .options
+                .max_buffer_length
+                .is_some_and(|max| bytes.len() >= max)
+            {
+                self.truncated = true;
+            } else {
+                bytes.push((f.to_digit(16).unwrap() * 0x10 + s.to_digit(16).unwrap()) as u8);
+            }
+        }
+    }
+
+    // Skip spaces and tabs inside a buffer literal, but only if a full byte
+    //  pair follows them on the same line. Otherwise, the whitespace
+    //  terminates the buffer.
+    fn skip_hex_whitespace(&mut self) {
+        let mut n = 0;
+        while matches!(self.peek_char(n), ' ' | '\t') {
+            n += 1;
+        }
+        if self.peek_char(n).is_ascii_hexdigit() && self.peek_char(n + 1).is_ascii_hexdigit() {
+            while matches!(self.next, ' ' | '\t') {
+                self.read_char();
+            }
+        }
+    }
+
+    // Report a line break in the body of a string, if strings may not span
+    //  multiple lines, or any raw tab or line ending character, if those
+    //  must be escaped. Returns whether an error was reported.
+    fn check_string_char(&mut self) -> bool {
+        let error = match self.next {
+            '\n' if self.options.forbid_multiline_strings => LexerError::NewlineInString,
+            '\t' | '\r' | '\n' if self.options.forbid_raw_control_chars => {
+                LexerError::RawControlInString
+            }
+            _ => return false,
+        };
+        self.errors.push(PlacedToken {
+            span: Span {
+                start_line: self.line as u32,
+                start_column: self.column as u32,
+                end_line: self.line as u32,
+                end_column: self.column as u32,
+            },
+            token: Token::Error(error),
+        });
+        true
+    }
+
+    // Keep a string being read within the configured limit. Once it is
+    //  reached, nothing more is stored, but the rest of the string is still
+    //  read, through its closing quote.
+    fn limit_string(&mut self, s: &mut String, kept: &mut Option<usize>) {
+        match *kept {
+            Some(len) => s.truncate(len),
+            None => {
+                if truncate_str(s, self.options.max_string_length) {
+                    *kept = Some(s.len());
+                    self.truncated = true;
+                }
+            }
         }
     }
 
+    // Report a NUL character in the input at `next`, which is not the end of
+    //  the input.
+    fn report_nul(&mut self) {
+        self.errors.push(PlacedToken {
+            span: Span {
+                start_line: self.line as u32,
+                start_column: self.column as u32,
+                end_line: self.line as u32,
+                end_column: self.column as u32,
+            },
+            token: Token::Error(LexerError::UnexpectedNul),
+        });
+    }
+
+    // Is `next` the start of a line ending (`\n` or `\r\n`)?
+    fn at_line_end(&mut self) -> bool {
+        self.next == '\n' || (self.next == '\r' && self.peek_char(0) == '\n')
+    }
+
     pub fn read_ascii_string(&mut self) -> String {
         let start_line = self.line as u32;
         let start_column = self.column as u32;
         let mut s = String::new();
         let mut escaped = false;
+        let mut kept = None;
+        self.truncated = false;
         self.read_char();
         loop {
+            if escaped
+                && (self.at_eof() || (self.options.string_line_recovery && self.at_line_end()))
+            {
+                // A backslash at the very end of the input, or of the line
+                //  when recovering there, has nothing to escape. Report it,
+                //  then the unterminated string.
+                self.errors.push(PlacedToken {
+                    span: Span {
+                        start_line: self.last_line as u32,
+                        start_column: self.last_column as u32,
+                        end_line: self.last_line as u32,
+                        end_column: self.last_column as u32,
+                    },
+                    token: Token::Error(LexerError::DanglingEscape),
+                });
+                escaped = false;
+            }
             if escaped {
-                let ch = match self.next {
-                    '\\' => '\\',
-                    '\"' => '\"',
-                    'n' => '\n',
-                    't' => '\t',
-                    'r' => '\r',
-                    '0' => '\0',
-                    _ => {
-                        self.errors.push(PlacedToken {
-                            span: Span {
-                                start_line: self.last_line as u32,
-                                start_column: self.last_column as u32,
-                                end_line: self.line as u32,
-                                end_column: self.column as u32,
-                            },
-                            token: Token::Error(LexerError::UnknownEscapeChar(self.next)),
-                        });
-                        '�'
-                    }
+                match self.next {
+                    '\\' => s.push('\\'),
+                    '\"' => s.push('\"'),
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    '0' => s.push('\0'),
+                    _ => self.unknown_escape(&mut s),
                 };
-                s.push(ch);
                 escaped = false;
             } else {
+                if self.options.string_line_recovery && self.at_line_end() {
+                    self.errors.push(PlacedToken {
+                        span: Span {
+                            start_line,
+                            start_column,
+                            end_line: self.last_line as u32,
+                            end_column: self.last_column as u32,
+                        },
+                        token: Token::Error(LexerError::UnterminatedString),
+                    });
+                    return s;
+                }
                 match self.next {
                     '"' => {
                         self.read_char();
                         return s;
                     }
                     '\\' => escaped = !escaped,
-                    '\0' => {
+                    '\0' if self.at_eof() => {
                         self.errors.push(PlacedToken {
                             span: Span {
                                 start_line: start_line,
@@ -236,8 +1196,18 @@ impl<'a> Lexer<'a> {
                         });
                         return s;
                     }
-                    _ => {
-                        if !self.next.is_ascii() {
+                    '\0' => {
+                        self.report_nul();
+                        s.push('\0');
+                    }
+                    _ => {
+                        // A raw control character is only reported once
+                        let reported = self.check_string_char();
+                        if !self.next.is_ascii()
+                            || (self.options.strict_ascii
+                                && self.next.is_ascii_control()
+                                && !reported)
+                        {
                             self.errors.push(PlacedToken {
                                 span: Span {
                                     start_line: self.line as u32,
@@ -253,6 +1223,7 @@ impl<'a> Lexer<'a> {
                     }
                 }
             }
+            self.limit_string(&mut s, &mut kept);
             self.read_char();
         }
     }
@@ -262,8 +1233,27 @@ impl<'a> Lexer<'a> {
         let start_column = self.last_column as u32;
         let mut s = String::new();
         let mut escaped = false;
+        let mut kept = None;
+        self.truncated = false;
         self.read_char();
         loop {
+            if escaped
+                && (self.at_eof() || (self.options.string_line_recovery && self.at_line_end()))
+            {
+                // A backslash at the very end of the input, or of the line
+                //  when recovering there, has nothing to escape. Report it,
+                //  then the unterminated string.
+                self.errors.push(PlacedToken {
+                    span: Span {
+                        start_line: self.last_line as u32,
+                        start_column: self.last_column as u32,
+                        end_line: self.last_line as u32,
+                        end_column: self.last_column as u32,
+                    },
+                    token: Token::Error(LexerError::DanglingEscape),
+                });
+                escaped = false;
+            }
             if escaped {
                 match self.next {
                     '\\' => s.push('\\'),
@@ -272,68 +1262,682 @@ impl<'a> Lexer<'a> {
                     't' => s.push('\t'),
                     'r' => s.push('\r'),
                     '0' => s.push('\0'),
-                    'u' => s.push_str("\\u"),
-                    _ => {
-                        self.errors.push(PlacedToken {
-                            span: Span {
-                                start_line: self.last_line as u32,
-                                start_column: self.last_column as u32,
-                                end_line: self.line as u32,
-                                end_column: self.column as u32,
-                            },
-                            token: Token::Error(LexerError::UnknownEscapeChar(self.next)),
-                        });
-                        s.push('�');
+                    'u' => {
+                        let escape_line = self.last_line as u32;
+                        let escape_column = self.last_column as u32;
+                        match self.read_unicode_escape() {
+                            Some(ch) => s.push(ch),
+                            None => {
+                                self.errors.push(PlacedToken {
+                                    span: Span {
+                                        start_line: escape_line,
+                                        start_column: escape_column,
+                                        end_line: self.line as u32,
+                                        end_column: self.column as u32,
+                                    },
+                                    token: Token::Error(LexerError::InvalidUnicodeEscape),
+                                });
+                                s.push(self.options.replacement_char);
+                            }
+                        }
                     }
+                    _ => self.unknown_escape(&mut s),
                 };
                 escaped = false;
             } else {
+                if self.options.string_line_recovery && self.at_line_end() {
+                    self.errors.push(PlacedToken {
+                        span: Span {
+                            start_line,
+                            start_column,
+                            end_line: self.last_line as u32,
+                            end_column: self.last_column as u32,
+                        },
+                        token: Token::Error(LexerError::UnterminatedString),
+                    });
+                    return s;
+                }
                 match self.next {
                     '"' => {
                         self.read_char();
                         return s;
                     }
                     '\\' => escaped = !escaped,
-                    '\0' => {
+                    '\0' if self.at_eof() => {
                         self.errors.push(PlacedToken {
                             span: Span {
                                 start_line,
                                 start_column,
-                                end_line: self.line as u32,
-                                end_column: self.column as u32,
+                                end_line: self.last_line as u32,
+                                end_column: self.last_column as u32,
                             },
                             token: Token::Error(LexerError::UnterminatedString),
                         });
                         return s;
                     }
+                    '\0' => {
+                        self.report_nul();
+                        s.push('\0');
+                    }
                     _ => {
+                        self.check_string_char();
                         escaped = false;
                         s.push(self.next);
                     }
                 }
             }
+            self.limit_string(&mut s, &mut kept);
+            self.read_char();
+        }
+    }
+
+    // Report the unknown escape `\` + `next` in a string, and recover as
+    //  `escape_errors` says.
+    fn unknown_escape(&mut self, s: &mut String) {
+        self.errors.push(PlacedToken {
+            span: Span {
+                start_line: self.last_line as u32,
+                start_column: self.last_column as u32,
+                end_line: self.line as u32,
+                end_column: self.column as u32,
+            },
+            token: Token::Error(LexerError::UnknownEscapeChar(self.next)),
+        });
+        match self.options.escape_errors {
+            EscapeErrorPolicy::Replace => s.push(self.options.replacement_char),
+            EscapeErrorPolicy::Keep => {
+                s.push('\\');
+                s.push(self.next);
+            }
+            EscapeErrorPolicy::Drop => (),
+        }
+    }
+
+    // Decode a `\u{...}` escape, starting with `next` on the `u`. Consumes
+    //  up to the last character of the escape, leaving it in `next`. Returns
+    //  `None` if the escape is malformed or does not name a Unicode scalar
+    //  value (e.g. a surrogate).
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.peek_char(0) != '{' {
+            return None;
+        }
+        self.read_char();
+
+        let mut value: u32 = 0;
+        let mut digits = 0;
+        loop {
+            let ch = self.peek_char(0);
+            if ch == '}' {
+                self.read_char();
+                break;
+            }
+            match ch.to_digit(16) {
+                Some(digit) if digits < 6 => {
+                    self.read_char();
+                    value = value * 16 + digit;
+                    digits += 1;
+                }
+                _ => {
+                    self.skip_unicode_escape();
+                    return None;
+                }
+            }
+        }
+        if digits == 0 {
+            return None;
+        }
+        char::from_u32(value)
+    }
+
+    // Skip the rest of an invalid `\u{...}` escape, through its closing `}`,
+    //  so that none of it ends up in the string. Stop early at anything which
+    //  cannot be part of the escape: a quote, a backslash or a line break.
+    fn skip_unicode_escape(&mut self) {
+        loop {
+            match self.peek_char(0) {
+                '}' => {
+                    self.read_char();
+                    return;
+                }
+                '"' | '\\' | '\n' | '\r' | '\0' => return,
+                _ => self.read_char(),
+            }
+        }
+    }
+
+    // Read a character literal, starting at its `#\` marker. The character
+    //  is written as itself (`#\a`), with one of the escapes allowed in
+    //  strings (`#\\n`), or by name (`#\newline`, `#\space`, `#\tab`).
+    pub fn read_char_literal(&mut self) -> char {
+        let start_line = self.line as u32;
+        let start_column = self.column as u32;
+        self.read_char();
+        self.read_char();
+
+        let mut text = String::new();
+        if !self.next.is_ascii_whitespace() && !self.at_eof() {
+            text.push(self.next);
+            self.read_char();
+            while !is_separator(self.next) {
+                text.push(self.next);
+                self.read_char();
+            }
+        }
+
+        let mut chars = text.chars();
+        let ch = match (chars.next(), chars.next(), chars.next()) {
+            (Some(ch), None, _) => Some(ch),
+            (Some('\\'), Some(escape), None) => match escape {
+                '\\' => Some('\\'),
+                '\"' => Some('\"'),
+                'n' => Some('\n'),
+                't' => Some('\t'),
+                'r' => Some('\r'),
+                '0' => Some('\0'),
+                _ => None,
+            },
+            _ => match text.as_str() {
+                "newline" => Some('\n'),
+                "space" => Some(' '),
+                "tab" => Some('\t'),
+                _ => None,
+            },
+        };
+        ch.unwrap_or_else(|| {
+            self.errors.push(PlacedToken {
+                span: Span {
+                    start_line,
+                    start_column,
+                    end_line: self.last_line as u32,
+                    end_column: self.last_column as u32,
+                },
+                token: Token::Error(LexerError::InvalidCharLiteral),
+            });
+            self.options.replacement_char
+        })
+    }
+
+    // Read what follows a `#` which does not start a character literal: an
+    //  attribute, written `#name` or `#[name]`, or otherwise just the `#`.
+    fn read_attribute(&mut self) -> Token {
+        let start_line = self.line as u32;
+        let start_column = self.column as u32;
+        self.read_char();
+        if self.next.is_ascii_alphabetic() {
+            return Token::Attribute(self.read_identifier(None).into_owned());
+        }
+        if self.next != '[' {
+            return Token::Hash;
+        }
+
+        self.read_char();
+        let mut name = String::new();
+        while self.next.is_ascii_alphanumeric() || matches!(self.next, '_' | '-' | '!' | '?') {
+            name.push(self.next);
+            self.read_char();
+        }
+        if self.next == ']' && !name.is_empty() {
             self.read_char();
+        } else {
+            while !is_separator(self.next) && self.next != ']' {
+                self.read_char();
+            }
+            if self.next == ']' {
+                self.read_char();
+            }
+            self.errors.push(PlacedToken {
+                span: Span {
+                    start_line,
+                    start_column,
+                    end_line: self.last_line as u32,
+                    end_column: self.last_column as u32,
+                },
+                token: Token::Error(LexerError::InvalidAttribute),
+            });
         }
+        Token::Attribute(name)
     }
 
     pub fn read_token(&mut self) -> PlacedToken {
+        if let Some(placed) = self.queued.pop_front() {
+            return placed;
+        }
+        let errors_before = self.errors.len();
+        loop {
+            let mut token = match self.pending.take() {
+                Some((token, errors)) => {
+                    self.errors.extend(errors);
+                    token
+                }
+                None => self.read_next_token(),
+            };
+            if self.options.collapse_errors {
+                self.extend_garbage_run(&mut token);
+            }
+            if !(self.options.skip_unknown_symbols && token.token == Token::Placeholder) {
+                self.check_length(&mut token);
+                if let Token::Ident(name) = &token.token {
+                    self.check_identifier(name, &token.span);
+                }
+                if self.options.collapse_errors {
+                    self.collapse_errors(errors_before);
+                }
+                self.track_depth(&token);
+                self.notify_errors(errors_before);
+                #[cfg(feature = "trace")]
+                self.trace.push(TraceEvent::ReadToken {
+                    kind: token.token.kind(),
+                    span: token.span.clone(),
+                });
+                if self.options.inline_errors {
+                    return self.interleave_errors(token, errors_before);
+                }
+                return token;
+            }
+        }
+    }
+
+    /// Call `observer` with each error as it is reported, so that a caller
+    /// can show diagnostics before lexing is finished. Errors are passed in
+    /// the order they are added to `errors`, once the token they were
+    /// reported with has been read. An error merged into an earlier one by
+    /// `collapse_errors` is not passed again.
+    pub fn on_error(&mut self, observer: impl FnMut(&PlacedToken) + 'a) {
+        self.on_error = Some(Box::new(observer));
+    }
+
+    // Pass each error reported since the `from`th to the `on_error`
+    //  observer, if there is one.
+    fn notify_errors(&mut self, from: usize) {
+        if let Some(observer) = &mut self.on_error {
+            self.errors.iter().skip(from).for_each(observer);
+        }
+    }
+
+    // Queue up `token` and the errors reported since the `from`th in the
+    //  order they appear in the input, returning the first. A `Placeholder`
+    //  is left out, since its error takes its place; if it has no error of
+    //  its own, the next token is returned instead.
+    fn interleave_errors(&mut self, token: PlacedToken, from: usize) -> PlacedToken {
+        let mut placed = vec![];
+        if token.token != Token::Placeholder {
+            placed.push(token);
+        }
+        placed.extend(self.errors[from..].iter().cloned());
+        // The sort is stable, so a token comes before an error at its start
+        placed.sort_by_key(|p| (p.span.start_line, p.span.start_column));
+        self.queued.extend(placed);
+        // A placeholder whose error was merged away leaves nothing to return
+        match self.queued.pop_front() {
+            Some(placed) => placed,
+            None => self.read_token(),
+        }
+    }
+
+    fn track_depth(&mut self, placed: &PlacedToken) {
+        match placed.token {
+            Token::Whitespace | Token::Indentation | Token::Newline | Token::Comment(_) => return,
+            Token::Lparen | Token::Lbrace => {
+                if self
+                    .options
+                    .max_delimiter_depth
+                    .is_some_and(|max| self.depth >= max)
+                {
+                    self.errors.push(PlacedToken {
+                        span: placed.span.clone(),
+                        token: Token::Error(LexerError::MaxDepthExceeded),
+                    });
+                    self.excess_depth += 1;
+                } else {
+                    self.depth += 1;
+                }
+            }
+            Token::Rparen | Token::Rbrace => {
+                if self.excess_depth > 0 {
+                    self.excess_depth -= 1;
+                } else {
+                    self.depth = self.depth.saturating_sub(1);
+                }
+            }
+            _ => (),
+        }
+        self.closed_form = matches!(placed.token, Token::Rparen | Token::Rbrace) && self.depth == 0;
+    }
+
+    /// How many characters were skipped by the most recent recovery from an
+    /// error inside a token, starting at the offending character (e.g.
+    /// the `abc` of `0abc`). Zero if there has been no such recovery.
+    pub fn last_recovery_len(&self) -> usize {
+        self.last_recovery_len
+    }
+
+    /// How many characters have been skipped by recovery from errors in
+    /// total.
+    pub fn total_recovery_len(&self) -> usize {
+        self.total_recovery_len
+    }
+
+    /// Is this a good place for a parser to resume after an error? That is
+    /// the case just after a top-level form is closed, or at a `(` at the
+    /// start of a line (which likely starts a new top-level form, even if
+    /// an earlier one was never closed).
+    pub fn is_resync_point(&self) -> bool {
+        (self.depth == 0 && self.closed_form) || (self.next == '(' && self.column == 1)
+    }
+
+    // Extend a `Placeholder` for an unknown symbol over any unknown symbols
+    //  directly after it, so that their errors can be collapsed together.
+    //  The token which ends the run is kept for the next `read_token`.
+    fn extend_garbage_run(&mut self, placed: &mut PlacedToken) {
+        while placed.token == Token::Placeholder {
+            let errors_before = self.errors.len();
+            let next = self.read_next_token();
+            let adjacent = placed.span.end_line == next.span.start_line
+                && placed.span.end_column + 1 == next.span.start_column;
+            if next.token != Token::Placeholder || !adjacent {
+                let errors = self.errors.split_off(errors_before);
+                self.pending = Some((next, errors));
+                return;
+            }
+            placed.span.end_line = next.span.end_line;
+            placed.span.end_column = next.span.end_column;
+        }
+    }
+
+    // Merge each error reported since the `from`th into the one before it,
+    //  when they are adjacent and similar enough to be reported as one.
+    //  Errors from earlier tokens have already been delivered, so they are
+    //  left alone.
+    fn collapse_errors(&mut self, from: usize) {
+        let mut i = from + 1;
+        while i < self.errors.len() {
+            let (prev, next) = (&self.errors[i - 1], &self.errors[i]);
+            let adjacent = prev.span.end_line == next.span.start_line
+                && prev.span.end_column + 1 == next.span.start_column;
+            let merged = match (&prev.token, &next.token) {
+                (Token::Error(a), Token::Error(b)) if adjacent => merge_errors(a, b),
+                _ => None,
+            };
+            match merged {
+                Some(error) => {
+                    let next = self.errors.remove(i);
+                    let prev = &mut self.errors[i - 1];
+                    prev.span.end_line = next.span.end_line;
+                    prev.span.end_column = next.span.end_column;
+                    prev.token = Token::Error(error);
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    // Report a token which is longer than the configured limit, truncating
+    //  an identifier. Strings and buffers are truncated as they are read.
+    fn check_length(&mut self, placed: &mut PlacedToken) {
+        let (too_long, error) = match &mut placed.token {
+            Token::Ident(s)
+            | Token::QualifiedName(s)
+            | Token::Attribute(s)
+            | Token::Annotation(s) => (
+                truncate_str(s, self.options.max_identifier_length),
+                LexerError::IdentifierTooLong,
+            ),
+            Token::AsciiString(_) | Token::Utf8String(_) => (
+                std::mem::take(&mut self.truncated),
+                LexerError::TokenTooLong,
+            ),
+            Token::Bytes(_) => (
+                std::mem::take(&mut self.truncated),
+                LexerError::BufferTooLong,
+            ),
+            _ => return,
+        };
+        if too_long {
+            self.errors.push(PlacedToken {
+                span: placed.span.clone(),
+                token: Token::Error(error),
+            });
+        }
+    }
+
+    // Report any advisories about an identifier that was just read.
+    fn check_identifier(&mut self, name: &str, span: &Span) {
+        if self.options.warn_keyword_shadow && KEYWORDS.contains(&name) {
+            self.warnings.push(PlacedToken {
+                span: span.clone(),
+                token: Token::Error(LexerError::KeywordShadow(name.to_string())),
+            });
+        }
+    }
+
+    /// The category of `token`, if it has one under this lexer's options.
+    pub fn category(&self, token: &Token) -> Option<TokenCategory> {
+        let Token::Ident(name) = token else {
+            return None;
+        };
+        let listed = |names: &Option<HashSet<String>>| {
+            names.as_ref().is_some_and(|names| names.contains(name))
+        };
+        if listed(&self.options.builtins) {
+            Some(TokenCategory::Builtin)
+        } else if listed(&self.options.type_names) {
+            Some(TokenCategory::Type)
+        } else {
+            None
+        }
+    }
+
+    /// Read up to `n` tokens, stopping early (with the `Eof` token included)
+    /// if the end of the input is reached. The lexer does not buffer any
+    /// tokens, so this reads from the same position `read_token` would.
+    pub fn read_tokens(&mut self, n: usize) -> Vec<PlacedToken> {
+        let mut tokens = Vec::with_capacity(n);
+        while tokens.len() < n {
+            let token = self.read_token();
+            let eof = token.token == Token::Eof;
+            tokens.push(token);
+            if eof {
+                break;
+            }
+        }
+        tokens
+    }
+
+    /// Read the next token, along with the comment which follows it on the
+    /// same line and any whitespace before that comment, for formatters to
+    /// keep a trailing comment with the token it explains. Nothing is
+    /// attached to whitespace, comments or `Eof`, or without a comment, so a
+    /// comment on the next line is read as a token of its own.
+    pub fn read_token_with_trailing_trivia(&mut self) -> (PlacedToken, Vec<PlacedToken>) {
+        let placed = self.read_token();
+        if matches!(
+            placed.token,
+            Token::Whitespace
+                | Token::Indentation
+                | Token::Newline
+                | Token::Comment(_)
+                | Token::Eof
+        ) {
+            return (placed, vec![]);
+        }
+        let line = placed.span.end_line;
+        let mut trivia = vec![self.read_token()];
+        if trivia[0].token == Token::Whitespace {
+            trivia.push(self.read_token());
+        }
+        let comment = trivia.last().unwrap();
+        if !(matches!(comment.token, Token::Comment(_)) && comment.span.start_line == line) {
+            // Put back what was read, in order
+            while let Some(next) = trivia.pop() {
+                self.queued.push_front(next);
+            }
+        }
+        (placed, trivia)
+    }
+
+    fn unknown_symbol(&mut self) -> Token {
+        self.errors.push(PlacedToken {
+            span: Span {
+                start_line: self.line as u32,
+                start_column: self.column as u32,
+                end_line: self.line as u32,
+                end_column: self.column as u32,
+            },
+            token: Token::Error(LexerError::UnknownSymbol(self.next)),
+        });
+        Token::Placeholder
+    }
+
+    // Does `next` start an identifier under `identifier_starts`? `-`, `!`
+    //  and `?` are tokens on their own, so they only start an identifier
+    //  when a name follows directly (`-foo`, but not `-`, `->` or `- foo`).
+    fn starts_identifier(&mut self) -> bool {
+        if !self.options.identifier_starts.contains(&self.next) {
+            return false;
+        }
+        match self.next {
+            '_' => true,
+            '-' | '!' | '?' => {
+                let next = self.peek_char(0);
+                next.is_ascii_alphabetic() || next == '_'
+            }
+            _ => false,
+        }
+    }
+
+    // The length in characters of the comment prefix starting at `next`, or
+    //  0 if there is none.
+    fn comment_prefix_len(&mut self) -> usize {
+        let prefixes = &self.options.comment_prefixes.0;
+        if !prefixes.iter().any(|prefix| prefix.starts_with(self.next)) {
+            return 0;
+        }
+        // Make sure the longest prefix fits in the lookahead
+        let longest = prefixes.iter().map(|p| p.chars().count()).max();
+        if let Some(longest @ 2..) = longest {
+            self.peek_char(longest - 2);
+        }
+
+        let upcoming = || std::iter::once(self.next).chain(self.lookahead.iter().copied());
+        self.options
+            .comment_prefixes
+            .0
+            .iter()
+            .map(|prefix| (prefix, prefix.chars().count()))
+            .filter(|(prefix, len)| *len > 0 && prefix.chars().eq(upcoming().take(*len)))
+            .map(|(_, len)| len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Read the rest of a line comment, after its prefix.
+    fn read_comment(&mut self) -> Token {
+        if !self.options.verbatim_comments {
+            while self.next == ' ' || self.next == '\t' {
+                self.read_char();
+            }
+        }
+        Token::Comment(self.read_line())
+    }
+
+    fn read_next_token(&mut self) -> PlacedToken {
         let start_line = self.line as u32;
         let start_column = self.column as u32;
         let mut advance = true;
 
+        let comment_prefix = self.comment_prefix_len();
+        let identifier_start = comment_prefix == 0 && self.starts_identifier();
+        let malformed_sign = comment_prefix == 0 && self.starts_malformed_sign();
         let token = match self.next {
-            '\0' => Token::Eof,
+            _ if comment_prefix > 0 => {
+                for _ in 0..comment_prefix {
+                    self.read_char();
+                }
+                advance = false;
+                self.read_comment()
+            }
+            _ if identifier_start => {
+                advance = false;
+                self.read_name(None)
+            }
+            // Signs are separate tokens from the integer they precede, but a
+            //  run of them, like `+-5`, is malformed. It is read as one
+            //  `Placeholder`, since no sign can be chosen for the integer.
+            _ if malformed_sign => {
+                advance = false;
+                while matches!(self.next, '+' | '-') {
+                    self.read_char();
+                }
+                self.read_int_token();
+                self.errors.push(PlacedToken {
+                    span: Span {
+                        start_line,
+                        start_column,
+                        end_line: self.last_line as u32,
+                        end_column: self.last_column as u32,
+                    },
+                    token: Token::Error(LexerError::MalformedNumber),
+                });
+                Token::Placeholder
+            }
+            '\0' if self.at_eof() => Token::Eof,
+            '\0' => {
+                self.errors.push(PlacedToken {
+                    span: Span {
+                        start_line,
+                        start_column,
+                        end_line: start_line,
+                        end_column: start_column,
+                    },
+                    token: Token::Error(LexerError::UnexpectedNul),
+                });
+                Token::Placeholder
+            }
             '(' => Token::Lparen,
             ')' => Token::Rparen,
             '{' => Token::Lbrace,
             '}' => Token::Rbrace,
-            ':' => Token::Colon,
-            '.' => Token::Dot,
+            ':' => {
+                self.read_char();
+                if self.next == ':' {
+                    Token::DoubleColon
+                } else {
+                    advance = false;
+                    Token::Colon
+                }
+            }
+            '.' => {
+                if self.peek_char(0) == '.' {
+                    self.read_char();
+                    if self.peek_char(0) == '.' {
+                        self.read_char();
+                        Token::Ellipsis
+                    } else {
+                        Token::DotDot
+                    }
+                } else if self.options.qualified_names && self.peek_char(0).is_ascii_alphabetic() {
+                    advance = false;
+                    let mut name = String::new();
+                    self.read_qualified_segments(&mut name);
+                    Token::QualifiedName(name)
+                } else {
+                    Token::Dot
+                }
+            }
             ',' => Token::Comma,
             '+' => Token::Plus,
-            '-' => Token::Minus,
+            '-' => {
+                self.read_char();
+                if self.next == '>' {
+                    Token::Arrow
+                } else {
+                    advance = false;
+                    Token::Minus
+                }
+            }
             '*' => Token::Multiply,
             '/' => Token::Divide,
+            '%' => Token::Percent,
             '<' => {
                 self.read_char();
                 if self.next == '=' {
@@ -352,43 +1956,97 @@ impl<'a> Lexer<'a> {
                     Token::Greater
                 }
             }
-            ';' => {
-                self.read_char();
-                if self.next != ';' {
-                    // If there is just one ';', report an error but continue
-                    //  parsing as if there were two (a comment).
+            '=' => {
+                if self.options.detect_operator_typos && matches!(self.peek_char(0), '<' | '>') {
+                    let (line, column) = (self.line, self.column);
+                    self.read_char();
+                    let (token, intended) = match self.next {
+                        '<' => (Token::LessEqual, "<="),
+                        _ => (Token::GreaterEqual, ">="),
+                    };
                     self.errors.push(PlacedToken {
                         span: Span {
-                            start_line: self.last_line as u32,
-                            start_column: self.last_column as u32,
-                            end_line: self.last_line as u32,
-                            end_column: self.last_column as u32,
+                            start_line: line as u32,
+                            start_column: column as u32,
+                            end_line: self.line as u32,
+                            end_column: self.column as u32,
                         },
-                        token: Token::Error(LexerError::SingleSemiColon),
+                        token: Token::Error(LexerError::LikelyTypo(intended.to_string())),
                     });
+                    token
                 } else {
-                    self.read_char();
+                    Token::Equal
+                }
+            }
+            // `!` and `?` are only standalone tokens at the start of a token
+            //  (e.g. `!foo` is `Bang` then `foo`); elsewhere they are part
+            //  of an identifier (e.g. `set!`, `is-ok?`).
+            '!' => {
+                self.read_char();
+                if self.next == '=' {
+                    Token::NotEqual
+                } else {
+                    advance = false;
+                    Token::Bang
                 }
+            }
+            '?' => Token::Question,
+            '\'' => Token::Quote,
+            ';' if self
+                .options
+                .comment_prefixes
+                .0
+                .iter()
+                .any(|prefix| prefix.starts_with(';')) =>
+            {
+                // If there is just one ';', report an error but continue
+                //  parsing as if it started a comment.
+                self.errors.push(PlacedToken {
+                    span: Span {
+                        start_line,
+                        start_column,
+                        end_line: start_line,
+                        end_column: start_column,
+                    },
+                    token: Token::Error(LexerError::SingleSemiColon),
+                });
+                self.read_char();
                 advance = false;
-                self.skip_whitespace();
-                let comment = self.read_line();
-                Token::Comment(comment)
+                self.read_comment()
             }
             'u' => {
                 advance = false;
                 self.read_char();
-                if self.next.is_ascii_digit() {
-                    Token::Uint(self.read_unsigned())
+                let radix = match (self.next, self.peek_char(0)) {
+                    ('0', 'b' | 'B') => Some(2),
+                    ('0', 'o' | 'O') => Some(8),
+                    _ => None,
+                };
+                if let Some(radix) = radix {
+                    self.read_char();
+                    Token::Uint(self.read_prefixed(radix, u128::MAX))
+                } else if self.next.is_ascii_digit() {
+                    self.read_uint_token()
                 } else if self.next == '"' {
                     Token::Utf8String(self.read_utf8_string())
                 } else {
-                    Token::Ident(self.read_identifier(Some('u')))
+                    self.read_name(Some('u'))
                 }
             }
             ' ' | '\t' | '\r' | '\n' => {
-                self.skip_whitespace();
-                advance = false;
-                Token::Whitespace
+                if self.options.significant_newlines && self.at_line_end() {
+                    if self.next == '\r' {
+                        self.read_char();
+                    }
+                    Token::Newline
+                } else {
+                    advance = false;
+                    if self.read_whitespace() && self.options.mark_indentation {
+                        Token::Indentation
+                    } else {
+                        Token::Whitespace
+                    }
+                }
             }
             '"' => {
                 advance = false;
@@ -397,34 +2055,73 @@ impl<'a> Lexer<'a> {
             '0' => {
                 advance = false;
                 self.read_char();
-                if self.next == 'x' {
+                // Radix prefixes may be written in either case
+                if matches!(self.next, 'x' | 'X') {
                     Token::Bytes(self.read_hex())
+                } else if matches!(self.next, 'b' | 'B') {
+                    Token::Int(self.read_prefixed(2, i128::MAX as u128) as i128)
+                } else if matches!(self.next, 'o' | 'O') {
+                    Token::Int(self.read_prefixed(8, i128::MAX as u128) as i128)
                 } else if self.next.is_ascii_digit() {
-                    Token::Int(self.read_integer())
+                    match self.options.leading_zeros {
+                        LeadingZeros::Ignore => self.read_int_token(),
+                        LeadingZeros::Error => {
+                            self.errors.push(PlacedToken {
+                                span: Span {
+                                    start_line,
+                                    start_column,
+                                    end_line: start_line,
+                                    end_column: start_column,
+                                },
+                                token: Token::Error(LexerError::LeadingZero),
+                            });
+                            self.read_int_token()
+                        }
+                        LeadingZeros::Octal => {
+                            Token::Int(self.read_radix(8, i128::MAX as u128) as i128)
+                        }
+                    }
                 } else if is_separator(self.next) {
                     Token::Int(0)
                 } else {
-                    self.proceed_through_error(LexerError::InvalidCharInt(self.next));
+                    self.proceed_through_number_error(
+                        start_line,
+                        start_column,
+                        LexerError::InvalidCharInt(self.next),
+                    );
                     Token::Int(0)
                 }
             }
+            // `@` directly followed by a name is an annotation, like an
+            //  attribute. `@` is not a separator, so `a@b` is still an
+            //  invalid identifier rather than an identifier and annotation.
+            '@' => {
+                self.read_char();
+                advance = false;
+                if self.next.is_ascii_alphabetic() {
+                    Token::Annotation(self.read_identifier(None).into_owned())
+                } else {
+                    Token::At
+                }
+            }
+            '#' => {
+                advance = false;
+                if self.peek_char(0) == '\\' {
+                    Token::Char(self.read_char_literal())
+                } else {
+                    self.read_attribute()
+                }
+            }
             _ => {
                 advance = false;
                 if self.next.is_ascii_alphabetic() {
-                    Token::Ident(self.read_identifier(None))
+                    self.read_name(None)
                 } else if self.next.is_ascii_digit() {
-                    Token::Int(self.read_integer())
+                    self.read_int_token()
                 } else {
-                    self.errors.push(PlacedToken {
-                        span: Span {
-                            start_line: self.line as u32,
-                            start_column: self.column as u32,
-                            end_line: self.line as u32,
-                            end_column: self.column as u32,
-                        },
-                        token: Token::Error(LexerError::UnknownSymbol(self.next)),
-                    });
-                    Token::Placeholder
+                    // Consume the unknown symbol so that lexing can continue
+                    advance = true;
+                    self.unknown_symbol()
                 }
             }
         };
@@ -558,207 +2255,2702 @@ mod tests {
             Token::Error(LexerError::UnknownEscapeChar('x'))
         );
 
-        lexer = Lexer::new("\"open");
+        lexer = Lexer::new("\"open");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("open".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnterminatedString)
+        );
+
+        lexer = Lexer::new("\"👎\"");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("👎".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IllegalCharString('👎'))
+        );
+
+        lexer = Lexer::new("\"\\u{1F600}\"");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("�{1F600}".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnknownEscapeChar('u'))
+        );
+
+        lexer = Lexer::new("u\"\\u{1F600}\"");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("😀".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 0);
+
+        lexer = Lexer::new("u\"quote \\\"this\\\"\"");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("quote \"this\"".to_string())
+        );
+
+        lexer = Lexer::new("u\"\\n\\r\\t\\0\\\\ ok\"");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("\n\r\t\0\\ ok".to_string())
+        );
+
+        lexer = Lexer::new("u\"\\x\"");
+        assert_eq!(lexer.read_token().token, Token::Utf8String("�".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnknownEscapeChar('x'))
+        );
+
+        lexer = Lexer::new("u\"open");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("open".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnterminatedString)
+        );
+
+        lexer = Lexer::new("0x123abc");
+        if let Token::Bytes(v) = lexer.read_token().token {
+            assert_eq!(v.len(), 3);
+            assert_eq!(v[0], 0x12);
+            assert_eq!(v[1], 0x3a);
+            assert_eq!(v[2], 0xbc);
+        } else {
+            assert!(false);
+        }
+
+        lexer = Lexer::new("0xdefg");
+        if let Token::Bytes(v) = lexer.read_token().token {
+            assert_eq!(v.len(), 1);
+            assert_eq!(v[0], 0xde);
+        } else {
+            assert!(false);
+        }
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharBuffer('g'))
+        );
+
+        lexer = Lexer::new("0xdef");
+        if let Token::Bytes(v) = lexer.read_token().token {
+            assert_eq!(v.len(), 1);
+            assert_eq!(v[0], 0xde);
+        } else {
+            assert!(false);
+        }
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidBufferLength(3))
+        );
+
+        lexer = Lexer::new("0x00p5");
+        if let Token::Bytes(v) = lexer.read_token().token {
+            assert_eq!(v.len(), 1);
+            assert_eq!(v[0], 0x0);
+        } else {
+            assert!(false);
+        }
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharBuffer('p'))
+        );
+
+        lexer = Lexer::new("0xdef0 ");
+        if let Token::Bytes(v) = lexer.read_token().token {
+            assert_eq!(v.len(), 2);
+            assert_eq!(v[0], 0xde);
+            assert_eq!(v[1], 0xf0);
+        } else {
+            assert!(false);
+        }
+
+        lexer = Lexer::new("foo");
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+
+        lexer = Lexer::new("ubar");
+        assert_eq!(lexer.read_token().token, Token::Ident("ubar".to_string()));
+
+        lexer = Lexer::new("baz👍buz");
+        assert_eq!(lexer.read_token().token, Token::Ident("baz".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharIdent('👍'))
+        );
+
+        lexer = Lexer::new("+");
+        assert_eq!(lexer.read_token().token, Token::Plus);
+
+        lexer = Lexer::new("-");
+        assert_eq!(lexer.read_token().token, Token::Minus);
+
+        lexer = Lexer::new("*");
+        assert_eq!(lexer.read_token().token, Token::Multiply);
+
+        lexer = Lexer::new("/");
+        assert_eq!(lexer.read_token().token, Token::Divide);
+
+        lexer = Lexer::new("%");
+        assert_eq!(lexer.read_token().token, Token::Percent);
+
+        // Like the other operators, `%` is not a separator
+        lexer = Lexer::new("a%b");
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharIdent('%'))
+        );
+
+        // Separated by whitespace, and at either end of the input
+        let tokens_of = |input| {
+            let (tokens, errors) = tokenize(input);
+            assert!(errors.is_empty(), "errors lexing {input:?}: {errors:?}");
+            tokens
+                .into_iter()
+                .map(|placed| placed.token)
+                .collect::<Vec<_>>()
+        };
+        let a = || Token::Ident("a".to_string());
+        let b = || Token::Ident("b".to_string());
+        assert_eq!(
+            tokens_of("a % b"),
+            [
+                a(),
+                Token::Whitespace,
+                Token::Percent,
+                Token::Whitespace,
+                b(),
+                Token::Eof
+            ]
+        );
+        assert_eq!(tokens_of("%a"), [Token::Percent, a(), Token::Eof]);
+        assert_eq!(
+            tokens_of("a %"),
+            [a(), Token::Whitespace, Token::Percent, Token::Eof]
+        );
+
+        lexer = Lexer::new("<");
+        assert_eq!(lexer.read_token().token, Token::Less);
+
+        lexer = Lexer::new("<=");
+        assert_eq!(lexer.read_token().token, Token::LessEqual);
+
+        lexer = Lexer::new(">");
+        assert_eq!(lexer.read_token().token, Token::Greater);
+
+        lexer = Lexer::new(">=");
+        assert_eq!(lexer.read_token().token, Token::GreaterEqual);
+
+        lexer = Lexer::new(";; this is a comment");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Comment("this is a comment".to_string())
+        );
+
+        lexer = Lexer::new(";; this is a comment\nthis is not");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Comment("this is a comment".to_string())
+        );
+
+        lexer = Lexer::new(";; this is a comment\r\n");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Comment("this is a comment".to_string())
+        );
+
+        lexer = Lexer::new("; this is not a comment");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Comment("this is not a comment".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::SingleSemiColon)
+        );
+
+        lexer = Lexer::new("~");
+        assert_eq!(lexer.read_token().token, Token::Placeholder);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnknownSymbol('~'))
+        );
+    }
+
+    #[test]
+    fn read_alternate_comments() {
+        let mut lexer = Lexer::builder()
+            .comment_prefixes(&["//", "#"])
+            .build("// one\n(a / b) # two\n;; three");
+        let tokens: Vec<Token> = lexer.read_tokens(16).into_iter().map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Comment("one".to_string()),
+                Token::Whitespace,
+                Token::Lparen,
+                Token::Ident("a".to_string()),
+                Token::Whitespace,
+                Token::Divide,
+                Token::Whitespace,
+                Token::Ident("b".to_string()),
+                Token::Rparen,
+                Token::Whitespace,
+                Token::Comment("two".to_string()),
+                Token::Whitespace,
+                Token::Placeholder,
+                Token::Placeholder,
+                Token::Whitespace,
+                Token::Ident("three".to_string()),
+            ]
+        );
+        // `;` no longer starts a comment
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnknownSymbol(';'))
+        );
+
+        // The longest matching prefix is used
+        let mut lexer = Lexer::builder()
+            .comment_prefixes(&[";", ";;"])
+            .build(";;; doc");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Comment("; doc".to_string())
+        );
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn read_hex_with_whitespace() {
+        let options = LexerOptions {
+            hex_whitespace: true,
+            ..Default::default()
+        };
+
+        let mut lexer = Lexer::new("0xdead");
+        let plain = lexer.read_token().token;
+        lexer = Lexer::with_options("0x de ad", options.clone());
+        assert_eq!(lexer.read_token().token, plain);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.errors.len(), 0);
+
+        lexer = Lexer::with_options("0x de ad be ef", options.clone());
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Bytes(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+
+        // Without the option, whitespace still terminates the buffer
+        lexer = Lexer::new("0xde ad");
+        assert_eq!(lexer.read_token().token, Token::Bytes(vec![0xde]));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("ad".to_string()));
+
+        // Whitespace followed by something other than a byte pair ends the
+        //  buffer, leaving the whitespace and the next token intact.
+        lexer = Lexer::with_options("0xde a)", options.clone());
+        let token = lexer.read_token();
+        assert_eq!(token.token, Token::Bytes(vec![0xde]));
+        assert_eq!(token.span.end_column, 4);
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+
+        // Only spaces and tabs within the line are skipped, without any
+        //  indentation warnings.
+        let line_options = LexerOptions {
+            warn_mixed_indentation: true,
+            ..options.clone()
+        };
+        lexer = Lexer::with_options("0x\n \tde", line_options.clone());
+        assert_eq!(lexer.read_token().token, Token::Bytes(vec![]));
+        assert!(lexer.warnings().is_empty());
+        lexer = Lexer::with_options("0xde \n ad", line_options);
+        assert_eq!(lexer.read_token().token, Token::Bytes(vec![0xde]));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert!(lexer.warnings().is_empty());
+
+        // Whitespace is never skipped between the nibbles of a byte
+        lexer = Lexer::with_options("0xd e", options);
+        assert_eq!(lexer.read_token().token, Token::Bytes(vec![]));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidBufferLength(1))
+        );
+    }
+
+    #[test]
+    fn skip_unknown_symbols() {
+        let mut lexer = Lexer::new("123 ~ abc");
+        let mut tokens = vec![];
+        loop {
+            let token = lexer.read_token().token;
+            if token == Token::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[2], Token::Placeholder);
+        assert_eq!(lexer.errors.len(), 1);
+
+        lexer = Lexer::with_options(
+            "123 ~ abc",
+            LexerOptions {
+                skip_unknown_symbols: true,
+                ..Default::default()
+            },
+        );
+        let mut skipped = vec![];
+        loop {
+            let token = lexer.read_token().token;
+            if token == Token::Eof {
+                break;
+            }
+            skipped.push(token);
+        }
+        assert_eq!(skipped.len(), 4);
+        assert!(!skipped.contains(&Token::Placeholder));
+        assert_eq!(skipped[3], Token::Ident("abc".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnknownSymbol('~'))
+        );
+    }
+
+    #[test]
+    fn strict_ascii_strings() {
+        let strict = LexerOptions {
+            strict_ascii: true,
+            ..Default::default()
+        };
+
+        let mut lexer = Lexer::new("\"a\tb\"");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("a\tb".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 0);
+
+        lexer = Lexer::with_options("\"a\tb\"", strict.clone());
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("a\tb".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IllegalCharString('\t'))
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 3
+            }
+        );
+
+        // With raw control characters forbidden too, a tab is reported once
+        let both = LexerOptions {
+            forbid_raw_control_chars: true,
+            ..strict.clone()
+        };
+        lexer = Lexer::with_options("\"a\tb\"", both);
+        lexer.read_token();
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::RawControlInString)
+        );
+
+        // The escaped form is still accepted
+        lexer = Lexer::with_options("\"a\\tb\"", strict);
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("a\tb".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 0);
+    }
+
+    #[test]
+    fn warn_keyword_shadow() {
+        let input = "(let ((true 1)) truely)";
+        let mut lexer = Lexer::new(input);
+        while lexer.read_token().token != Token::Eof {}
+        assert!(lexer.warnings().is_empty());
+
+        lexer = Lexer::with_options(
+            input,
+            LexerOptions {
+                warn_keyword_shadow: true,
+                ..Default::default()
+            },
+        );
+        while lexer.read_token().token != Token::Eof {}
+        assert!(lexer.errors.is_empty());
+        assert_eq!(lexer.warnings().len(), 1);
+        assert_eq!(
+            lexer.warnings()[0].token,
+            Token::Error(LexerError::KeywordShadow("true".to_string()))
+        );
+        assert_eq!(
+            lexer.warnings()[0].span,
+            Span {
+                start_line: 1,
+                start_column: 8,
+                end_line: 1,
+                end_column: 11
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "graphemes")]
+    fn count_grapheme_columns() {
+        use crate::lexer::options::ColumnMode;
+
+        // `e` followed by a combining acute accent
+        let input = "u\"e\u{301}x\" y";
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.read_token().span.end_column, 6);
+        lexer.read_token();
+        assert_eq!(lexer.read_token().span.start_column, 8);
+
+        let mut lexer = Lexer::builder()
+            .column_mode(ColumnMode::Graphemes)
+            .build(input);
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Utf8String("e\u{301}x".to_string()));
+        assert_eq!(placed.span.end_column, 5);
+        lexer.read_token();
+        assert_eq!(lexer.read_token().span.start_column, 7);
+
+        // A flag is two regional indicators, but one grapheme cluster
+        let mut lexer = Lexer::builder()
+            .column_mode(ColumnMode::Graphemes)
+            .build("u\"\u{1F1FA}\u{1F1F8}\u{1F1FA}\u{1F1F8}\"\nx");
+        assert_eq!(lexer.read_token().span.end_column, 5);
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!((placed.span.start_line, placed.span.start_column), (2, 1));
+    }
+
+    #[test]
+    fn render_tokens() {
+        let source = "(define-data-var count uint u0)\n(map-set m {k: 1} \"a\\\"b\\n\") ;; note\n#\\space 0xbeef";
+        let (tokens, _) = tokenize(source);
+        assert_eq!(render(&tokens), source);
+
+        // Whitespace and literals are normalized, but lex to the same tokens
+        let source = "(foo   0x0A\n\n  -0042 u\"\\u{e9}\");;x\n";
+        let (tokens, _) = tokenize(source);
+        let rendered = render(&tokens);
+        assert_eq!(rendered, "(foo 0x0a\n-42 u\"é\");; x\n");
+        assert!(token::token_seq_eq(&tokenize(&rendered).0, &tokens));
+    }
+
+    #[test]
+    fn format_errors() {
+        let source = "(foo\n  (bar ~ baz))";
+        let (_, errors) = tokenize(source);
+        assert_eq!(
+            format_error(source, &errors[0]),
+            "\
+error: unknown symbol '~'
+ --> 2:8
+  |
+2 |   (bar ~ baz))
+  |        ^
+"
+        );
+
+        // Only the first line of a multi-line span is underlined
+        let source = "\tx \"ab\ncd";
+        let (_, errors) = tokenize(source);
+        assert_eq!(errors[0].span.end_line, 2);
+        assert_eq!(
+            format_error(source, &errors[0]),
+            "\
+error: unterminated string
+ --> 1:4
+  |
+1 | \tx \"ab
+  | \t  ^^^
+"
+        );
+
+        // A backslash at the end of a line, when strings recover there
+        let source = "\"ab\\\ncd";
+        let mut lexer = Lexer::builder().string_line_recovery(true).build(source);
+        lexer.read_token();
+        assert_eq!(
+            format_error(source, &lexer.errors[0]),
+            "\
+error: backslash with nothing to escape
+ --> 1:4
+  |
+1 | \"ab\\
+  |    ^
+"
+        );
+    }
+
+    #[test]
+    fn split_top_level_forms() {
+        let input = ";; first\n(define-constant a u1)\n\n(define-data-var b int 2)\n";
+        let forms = top_level_forms(input);
+        assert_eq!(forms.len(), 2);
+
+        let (span, tokens) = &forms[0];
+        assert_eq!(
+            span,
+            &Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 2,
+                end_column: 22
+            }
+        );
+        assert_eq!(tokens[0].token, Token::Comment("first".to_string()));
+        assert_eq!(tokens[2].token, Token::Lparen);
+        assert_eq!(tokens.last().unwrap().token, Token::Rparen);
+
+        let (span, tokens) = &forms[1];
+        assert_eq!(
+            span,
+            &Span {
+                start_line: 4,
+                start_column: 1,
+                end_line: 4,
+                end_column: 25
+            }
+        );
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(tokens[0].token, Token::Lparen);
+    }
+
+    #[test]
+    fn split_unbalanced_forms() {
+        let forms = top_level_forms(") (a (b)) (c ;; trailing\n");
+        let tokens: Vec<Vec<Token>> = forms
+            .into_iter()
+            .map(|(_, tokens)| tokens.into_iter().map(|placed| placed.token).collect())
+            .collect();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0], vec![Token::Rparen]);
+        assert_eq!(tokens[1].len(), 7);
+        assert_eq!(
+            tokens[2],
+            vec![
+                Token::Lparen,
+                Token::Ident("c".to_string()),
+                Token::Whitespace,
+                Token::Comment("trailing".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_significant_newlines() {
+        let mut lexer = Lexer::builder().significant_newlines(true).build("a\nb");
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Newline);
+        assert_eq!(
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 2,
+                end_line: 1,
+                end_column: 2
+            }
+        );
+        assert_eq!(lexer.read_token().token, Token::Ident("b".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        // Spaces around a line ending are still whitespace, and a `\r\n`
+        //  is a single newline
+        let mut lexer = Lexer::builder()
+            .significant_newlines(true)
+            .build("a \r\n\n  b");
+        let tokens: Vec<Token> = lexer.read_tokens(6).into_iter().map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("a".to_string()),
+                Token::Whitespace,
+                Token::Newline,
+                Token::Newline,
+                Token::Whitespace,
+                Token::Ident("b".to_string()),
+            ]
+        );
+
+        // By default, newlines are just whitespace
+        let mut lexer = Lexer::new("a\nb");
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+    }
+
+    #[test]
+    fn distinguish_indentation() {
+        let input = "  (a  b)\n\t(c) ";
+        let whitespace = |lexer: &mut Lexer| -> Vec<Token> {
+            lexer
+                .read_tokens(12)
+                .into_iter()
+                .map(|placed| placed.token)
+                .filter(|token| matches!(token, Token::Whitespace | Token::Indentation))
+                .collect()
+        };
+        let mut lexer = Lexer::builder().mark_indentation(true).build(input);
+        assert_eq!(
+            whitespace(&mut lexer),
+            vec![
+                Token::Indentation,
+                Token::Whitespace,
+                Token::Indentation,
+                Token::Whitespace,
+            ]
+        );
+
+        // Not marked by default
+        let mut lexer = Lexer::new(input);
+        assert_eq!(whitespace(&mut lexer), vec![Token::Whitespace; 4]);
+    }
+
+    #[test]
+    fn warn_mixed_indentation() {
+        let options = LexerOptions {
+            warn_mixed_indentation: true,
+            ..Default::default()
+        };
+        let input = "(a\n\t  b\n\t\tc \t d)";
+        let mut lexer = Lexer::with_options(input, options.clone());
+        while lexer.read_token().token != Token::Eof {}
+        assert!(lexer.errors.is_empty());
+        assert_eq!(lexer.warnings().len(), 1);
+        assert_eq!(
+            lexer.warnings()[0].token,
+            Token::Error(LexerError::MixedIndentation)
+        );
+        assert_eq!(
+            lexer.warnings()[0].span,
+            Span {
+                start_line: 2,
+                start_column: 1,
+                end_line: 2,
+                end_column: 3
+            }
+        );
+
+        // Consistent indentation, or mixed whitespace within a line, is fine
+        lexer = Lexer::with_options("  (a\n    b \t c)\n\t\td", options);
+        while lexer.read_token().token != Token::Eof {}
+        assert!(lexer.warnings().is_empty());
+
+        // Off by default
+        lexer = Lexer::new(input);
+        while lexer.read_token().token != Token::Eof {}
+        assert!(lexer.warnings().is_empty());
+    }
+
+    #[test]
+    fn classify_builtins() {
+        use crate::lexer::token::BUILTINS;
+
+        let input = "(tx-sender my-var \"tx-sender\")";
+        let mut lexer = Lexer::builder().builtins(BUILTINS).build(input);
+        let tokens = lexer.read_tokens(6);
+        assert_eq!(tokens[1].token, Token::Ident("tx-sender".to_string()));
+        assert_eq!(
+            lexer.category(&tokens[1].token),
+            Some(TokenCategory::Builtin)
+        );
+        assert_eq!(lexer.category(&tokens[3].token), None);
+        assert_eq!(lexer.category(&tokens[5].token), None);
+
+        // Off by default
+        let lexer = Lexer::new(input);
+        assert_eq!(lexer.category(&tokens[1].token), None);
+
+        let lexer = Lexer::builder().builtins(&["my-var"]).build(input);
+        assert_eq!(lexer.category(&tokens[1].token), None);
+        assert_eq!(
+            lexer.category(&tokens[3].token),
+            Some(TokenCategory::Builtin)
+        );
+    }
+
+    #[test]
+    fn classify_type_names() {
+        use crate::lexer::token::{BUILTINS, TYPE_NAMES};
+
+        let input = "(uint my-uint string-ascii tx-sender)";
+        let mut lexer = Lexer::builder()
+            .builtins(BUILTINS)
+            .type_names(TYPE_NAMES)
+            .build(input);
+        let tokens = lexer.read_tokens(8);
+        assert_eq!(tokens[1].token, Token::Ident("uint".to_string()));
+        assert_eq!(lexer.category(&tokens[1].token), Some(TokenCategory::Type));
+        assert_eq!(lexer.category(&tokens[3].token), None);
+        assert_eq!(lexer.category(&tokens[5].token), Some(TokenCategory::Type));
+        assert_eq!(
+            lexer.category(&tokens[7].token),
+            Some(TokenCategory::Builtin)
+        );
+
+        // Off by default
+        let lexer = Lexer::new(input);
+        assert_eq!(lexer.category(&tokens[1].token), None);
+    }
+
+    #[test]
+    fn read_multi_char_operators() {
+        let mut lexer = Lexer::new("->");
+        assert_eq!(lexer.read_token().token, Token::Arrow);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("-");
+        assert_eq!(lexer.read_token().token, Token::Minus);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("-5");
+        assert_eq!(lexer.read_token().token, Token::Minus);
+        assert_eq!(lexer.read_token().token, Token::Int(5));
+
+        lexer = Lexer::new("::");
+        assert_eq!(lexer.read_token().token, Token::DoubleColon);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new(":");
+        assert_eq!(lexer.read_token().token, Token::Colon);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("a:b");
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Colon);
+        assert_eq!(lexer.read_token().token, Token::Ident("b".to_string()));
+    }
+
+    #[test]
+    fn read_dots() {
+        let mut lexer = Lexer::new(". .. ...");
+        assert_eq!(lexer.read_token().token, Token::Dot);
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::DotDot);
+        assert_eq!(
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 4
+            }
+        );
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Ellipsis);
+        assert_eq!(
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 6,
+                end_line: 1,
+                end_column: 8
+            }
+        );
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("....");
+        assert_eq!(lexer.read_token().token, Token::Ellipsis);
+        assert_eq!(lexer.read_token().token, Token::Dot);
+
+        lexer = Lexer::new("a.b.c");
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Dot);
+        assert_eq!(lexer.read_token().token, Token::Ident("b".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Dot);
+        assert_eq!(lexer.read_token().token, Token::Ident("c".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn operator_spans() {
+        let operators = [
+            ("<", Token::Less),
+            ("<=", Token::LessEqual),
+            (">", Token::Greater),
+            (">=", Token::GreaterEqual),
+            ("+", Token::Plus),
+            ("-", Token::Minus),
+            ("*", Token::Multiply),
+            ("/", Token::Divide),
+            ("%", Token::Percent),
+            ("=", Token::Equal),
+            ("!=", Token::NotEqual),
+            ("->", Token::Arrow),
+        ];
+        for (text, token) in operators {
+            let end_column = 2 + text.len() as u32;
+            // Separated by whitespace, and directly followed by other tokens
+            for input in [format!("a {} b", text), format!("(({}(", text)] {
+                let mut lexer = Lexer::new(&input);
+                let tokens = lexer.read_tokens(4);
+                assert_eq!(tokens[2].token, token, "{}", input);
+                assert_eq!(
+                    tokens[2].span,
+                    Span {
+                        start_line: 1,
+                        start_column: 3,
+                        end_line: 1,
+                        end_column,
+                    },
+                    "{}",
+                    input
+                );
+                assert_eq!(tokens[3].span.start_column, end_column + 1, "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn read_equality_operators() {
+        let mut lexer = Lexer::new("=");
+        assert_eq!(lexer.read_token().token, Token::Equal);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("!=");
+        assert_eq!(lexer.read_token().token, Token::NotEqual);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("(= a b)");
+        assert_eq!(lexer.read_token().token, Token::Lparen);
+        assert_eq!(lexer.read_token().token, Token::Equal);
+
+        lexer = Lexer::new("foo! != bar");
+        assert_eq!(lexer.read_token().token, Token::Ident("foo!".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::NotEqual);
+        assert_eq!(lexer.errors.len(), 0);
+    }
+
+    #[test]
+    fn detect_operator_typos() {
+        let (tokens, errors) = tokenize("(=< a b)");
+        assert_eq!(tokens[1].token, Token::Equal);
+        assert_eq!(tokens[2].token, Token::Less);
+        assert!(errors.is_empty());
+
+        let options = LexerOptions {
+            detect_operator_typos: true,
+            ..Default::default()
+        };
+        for (input, token, intended) in [
+            ("(=< a b)", Token::LessEqual, "<="),
+            ("(=> a b)", Token::GreaterEqual, ">="),
+        ] {
+            let mut lexer = Lexer::with_options(input, options.clone());
+            lexer.read_token();
+            let placed = lexer.read_token();
+            assert_eq!(placed.token, token);
+            assert_eq!(
+                placed.span,
+                Span {
+                    start_line: 1,
+                    start_column: 2,
+                    end_line: 1,
+                    end_column: 3
+                }
+            );
+            assert_eq!(lexer.read_token().token, Token::Whitespace);
+            assert_eq!(lexer.errors.len(), 1);
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::LikelyTypo(intended.to_string()))
+            );
+            assert_eq!(lexer.errors[0].span, placed.span);
+        }
+
+        // `=` alone is unaffected
+        let mut lexer = Lexer::with_options("(= a b)", options);
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Equal);
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn read_bang_and_question() {
+        let mut lexer = Lexer::new("?");
+        assert_eq!(lexer.read_token().token, Token::Question);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("!");
+        assert_eq!(lexer.read_token().token, Token::Bang);
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        lexer = Lexer::new("!foo");
+        assert_eq!(lexer.read_token().token, Token::Bang);
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+
+        lexer = Lexer::new("(x? set!)");
+        assert_eq!(lexer.read_token().token, Token::Lparen);
+        assert_eq!(lexer.read_token().token, Token::Ident("x?".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("set!".to_string()));
+        assert_eq!(lexer.errors.len(), 0);
+    }
+
+    #[test]
+    fn read_quote() {
+        let mut lexer = Lexer::new("' 'abc");
+        assert_eq!(lexer.read_token().token, Token::Quote);
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Quote);
+        assert_eq!(lexer.read_token().token, Token::Ident("abc".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert!(lexer.errors.is_empty());
+
+        // A quote cannot appear inside an identifier
+        lexer = Lexer::new("a'b");
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharIdent('\''))
+        );
+    }
+
+    #[test]
+    fn span_convention() {
+        let span = |start_column, end_column| Span {
+            start_line: 1,
+            start_column,
+            end_line: 1,
+            end_column,
+        };
+
+        // A single character token starts and ends at that character
+        let mut lexer = Lexer::new("(ab");
+        assert_eq!(lexer.read_token().span, span(1, 1));
+        assert_eq!(lexer.read_token().span, span(2, 3));
+        // `Eof` is zero-width, just past the last character
+        assert_eq!(lexer.read_token().span, span(4, 4));
+        assert_eq!(Lexer::new("").read_token().span, span(1, 1));
+
+        // Errors which run to the end of the input end at its last character
+        for input in ["\"ab", "u\"ab"] {
+            let mut lexer = Lexer::new(input);
+            let placed = lexer.read_token();
+            assert_eq!(placed.span, lexer.errors[0].span);
+            assert_eq!(lexer.errors[0].span.end_column, input.len() as u32);
+        }
+    }
+
+    #[test]
+    fn tokenize_input() {
+        let (tokens, errors) = tokenize("(+ 1 ~)");
+        let tokens: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Lparen,
+                Token::Plus,
+                Token::Whitespace,
+                Token::Int(1),
+                Token::Whitespace,
+                Token::Placeholder,
+                Token::Rparen,
+                Token::Eof
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].token,
+            Token::Error(LexerError::UnknownSymbol('~'))
+        );
+    }
+
+    #[test]
+    fn dump_tokens() {
+        assert_eq!(
+            debug_dump("(foo \"bar\")\n~"),
+            r#"1:1..1:1: Lparen
+1:2..1:4: Ident("foo")
+1:5..1:5: Whitespace
+1:6..1:10: AsciiString("bar")
+1:11..1:11: Rparen
+1:12..1:12: Whitespace
+2:1..2:1: Placeholder
+2:2..2:2: Eof
+2:1..2:1: Error(UnknownSymbol('~'))
+"#
+        );
+    }
+
+    #[test]
+    fn strip_all_comments() {
+        let (stripped, comments) = strip_comments(";; c\nfoo");
+        assert_eq!(stripped, "\nfoo");
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].token, Token::Comment("c".to_string()));
+
+        let (stripped, comments) = strip_comments("(a \"b ;; c\") ;; d\r\n  e ;; é");
+        assert_eq!(stripped, "(a \"b ;; c\") \r\n  e ");
+        assert_eq!(comments.len(), 2);
+
+        let (stripped, comments) = strip_comments("no comments");
+        assert_eq!(stripped, "no comments");
+        assert!(comments.is_empty());
+    }
+
+    #[test]
+    fn normalize_whitespace_between_tokens() {
+        assert_eq!(
+            normalize_whitespace("(foo  \t\"a  b\"   1)   \n"),
+            "(foo \"a  b\" 1)\n"
+        );
+        assert_eq!(
+            normalize_whitespace("  (a   b) ;;  keep   this  \r\n\t \n    c  "),
+            "  (a b) ;;  keep   this  \r\n\n    c"
+        );
+        assert_eq!(normalize_whitespace(""), "");
+    }
+
+    #[test]
+    fn find_blank_line_runs() {
+        let input = "(a)\n\n  \n\t\n(b) ;; c\n\n(d)\n\n\n";
+        assert_eq!(
+            blank_line_runs(input),
+            vec![
+                Span {
+                    start_line: 2,
+                    start_column: 1,
+                    end_line: 4,
+                    end_column: 2
+                },
+                Span {
+                    start_line: 8,
+                    start_column: 1,
+                    end_line: 9,
+                    end_column: 1
+                },
+            ]
+        );
+        assert!(blank_line_runs("(a)\n\n(b)\n").is_empty());
+        assert!(blank_line_runs("").is_empty());
+    }
+
+    #[test]
+    fn read_only_comments() {
+        let input = r#";; first
+(define-data-var counter int 0) ;; second
+"not ;; a comment"
+  ; third
+"#;
+        let comments = comments(input);
+        assert_eq!(comments.len(), 3);
+        assert_eq!(comments[0].token, Token::Comment("first".to_string()));
+        assert_eq!(
+            comments[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 8
+            }
+        );
+        assert_eq!(comments[1].token, Token::Comment("second".to_string()));
+        assert_eq!(
+            comments[1].span,
+            Span {
+                start_line: 2,
+                start_column: 33,
+                end_line: 2,
+                end_column: 41
+            }
+        );
+        assert_eq!(comments[2].token, Token::Comment("third".to_string()));
+        assert_eq!(
+            comments[2].span,
+            Span {
+                start_line: 4,
+                start_column: 3,
+                end_line: 4,
+                end_column: 9
+            }
+        );
+    }
+
+    #[test]
+    fn read_verbatim_comments() {
+        let mut lexer = Lexer::new(";;   hi");
+        assert_eq!(lexer.read_token().token, Token::Comment("hi".to_string()));
+
+        lexer = Lexer::with_options(
+            ";;   hi",
+            LexerOptions {
+                verbatim_comments: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Comment("   hi".to_string())
+        );
+
+        // Trimming never continues onto the next line
+        lexer = Lexer::new(";;  \nfoo");
+        assert_eq!(lexer.read_token().token, Token::Comment("".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+    }
+
+    #[test]
+    fn recover_unterminated_string_at_newline() {
+        let mut lexer = Lexer::new("\"open\nfoo");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("open\nfoo".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        let options = LexerOptions {
+            string_line_recovery: true,
+            ..Default::default()
+        };
+        lexer = Lexer::with_options("\"open\nfoo", options.clone());
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("open".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnterminatedString)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 5
+            }
+        );
+
+        // A backslash does not escape the line break
+        lexer = Lexer::with_options("\"abc\\\nfoo", options.clone());
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("abc".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+        let errors: Vec<&Token> = lexer.errors.iter().map(|e| &e.token).collect();
+        assert_eq!(
+            errors,
+            [
+                &Token::Error(LexerError::DanglingEscape),
+                &Token::Error(LexerError::UnterminatedString)
+            ]
+        );
+        assert_eq!(lexer.errors[1].span.end_line, 1);
+
+        lexer = Lexer::with_options("u\"open\r\nfoo", options);
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("open".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnterminatedString)
+        );
+    }
+
+    #[test]
+    fn limit_identifier_length() {
+        let longest = "a".repeat(128);
+        let mut lexer = Lexer::new(&longest);
+        assert_eq!(lexer.read_token().token, Token::Ident(longest.clone()));
+        assert!(lexer.errors.is_empty());
+
+        let input = format!("{longest}b c");
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(lexer.read_token().token, Token::Ident(longest.clone()));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Ident("c".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IdentifierTooLong)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 129
+            }
+        );
+
+        // Other tokens holding a name are limited in the same way
+        let name = "a".repeat(101);
+        let names = format!("{name}.{name} #{longest}b #[{longest}b] @{longest}b");
+        let mut lexer = Lexer::builder().qualified_names(true).build(&names);
+        let tokens: Vec<Token> = lexer
+            .read_tokens(8)
+            .into_iter()
+            .map(|placed| placed.token)
+            .filter(|token| !matches!(token, Token::Whitespace))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::QualifiedName(format!("{name}.{name}")[..128].to_string()),
+                Token::Attribute(longest.clone()),
+                Token::Attribute(longest.clone()),
+                Token::Annotation(longest.clone()),
+                Token::Eof,
+            ]
+        );
+        assert_eq!(lexer.errors.len(), 4);
+        assert!(lexer
+            .errors
+            .iter()
+            .all(|e| e.token == Token::Error(LexerError::IdentifierTooLong)));
+
+        // The limit can be lifted
+        let mut lexer = Lexer::builder().max_identifier_length(None).build(&input);
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Ident(format!("{longest}b"))
+        );
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn limit_token_length() {
+        let options = LexerOptions {
+            max_identifier_length: Some(4),
+            max_string_length: Some(4),
+            max_buffer_length: Some(2),
+            ..Default::default()
+        };
+
+        let mut lexer = Lexer::with_options("abcd abcdefg", options.clone());
+        assert_eq!(lexer.read_token().token, Token::Ident("abcd".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("abcd".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IdentifierTooLong)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 6,
+                end_line: 1,
+                end_column: 12
+            }
+        );
+
+        lexer = Lexer::with_options("\"hello\" u\"abcé\"", options.clone());
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("hell".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        // Truncated on a character boundary
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("abc".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 2);
+        assert_eq!(
+            lexer.errors[1].token,
+            Token::Error(LexerError::TokenTooLong)
+        );
+
+        // Nothing more is stored once the limit is reached, but the rest of
+        //  the string is still read through its closing quote.
+        lexer = Lexer::with_options("u\"abcéxy\\\"z\" w", options.clone());
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("abc".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("w".to_string()));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(lexer.errors[0].span.end_column, 12);
+
+        lexer = Lexer::with_options("0x010203 0x0102", options);
+        assert_eq!(lexer.read_token().token, Token::Bytes(vec![1, 2]));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Bytes(vec![1, 2]));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::BufferTooLong)
+        );
+        assert_eq!(lexer.errors[0].span.end_column, 8);
+
+        // No limits by default
+        lexer = Lexer::new("abcdefg");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Ident("abcdefg".to_string())
+        );
+        assert_eq!(lexer.errors.len(), 0);
+    }
+
+    #[test]
+    fn collapse_adjacent_errors() {
+        let options = LexerOptions {
+            collapse_errors: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options("~~~ a $&^ \"\\x\\x\"", options.clone());
+        while lexer.read_token().token != Token::Eof {}
+        let span = |start_column, end_column| Span {
+            start_line: 1,
+            start_column,
+            end_line: 1,
+            end_column,
+        };
+        assert_eq!(
+            lexer.errors(),
+            &[
+                PlacedToken {
+                    span: span(1, 3),
+                    token: Token::Error(LexerError::UnknownSymbol('~')),
+                },
+                PlacedToken {
+                    span: span(7, 9),
+                    token: Token::Error(LexerError::GarbageRun),
+                },
+                PlacedToken {
+                    span: span(12, 15),
+                    token: Token::Error(LexerError::UnknownEscapeChar('x')),
+                },
+            ]
+        );
+
+        // A run of unknown symbols is one token, and errors are not changed
+        //  once they have been passed to the observer
+        let delivered = std::cell::RefCell::new(vec![]);
+        let mut lexer = Lexer::with_options("~~ $&", options);
+        lexer.on_error(|error| delivered.borrow_mut().push(error.clone()));
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Placeholder);
+        assert_eq!(placed.span, span(1, 2));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().span, span(4, 5));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.errors().len(), 2);
+        assert_eq!(
+            lexer.errors()[1].token,
+            Token::Error(LexerError::GarbageRun)
+        );
+        let errors = lexer.errors().to_vec();
+        drop(lexer);
+        assert_eq!(delivered.into_inner(), errors);
+
+        // Off by default
+        let mut lexer = Lexer::new("~~~");
+        while lexer.read_token().token != Token::Eof {}
+        assert_eq!(lexer.error_count(), 3);
+    }
+
+    #[test]
+    fn inline_collapsed_errors() {
+        let mut lexer = LexerBuilder::new()
+            .inline_errors(true)
+            .collapse_errors(true)
+            .build("~~ a");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Error(LexerError::UnknownSymbol('~'))
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+
+        for input in ["~~", "O\rO< 😀\u{301}"] {
+            let mut lexer = LexerBuilder::new()
+                .inline_errors(true)
+                .collapse_errors(true)
+                .build(input);
+            let mut count = 0;
+            while lexer.read_token().token != Token::Eof {
+                count += 1;
+                assert!(count <= input.len(), "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn read_inline_errors() {
+        let input = "(a ~ 0b12)";
+        let kinds = |lexer: &mut Lexer| {
+            let mut tokens = vec![];
+            loop {
+                let placed = lexer.read_token();
+                if placed.token == Token::Eof {
+                    return tokens;
+                }
+                tokens.push(placed.token);
+            }
+        };
+
+        let mut lexer = Lexer::new(input);
+        assert_eq!(
+            kinds(&mut lexer),
+            vec![
+                Token::Lparen,
+                Token::Ident("a".to_string()),
+                Token::Whitespace,
+                Token::Placeholder,
+                Token::Whitespace,
+                Token::Int(1),
+                Token::Rparen,
+            ]
+        );
+        assert_eq!(lexer.error_count(), 2);
+
+        let options = LexerOptions {
+            inline_errors: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options(input, options);
+        assert_eq!(
+            kinds(&mut lexer),
+            vec![
+                Token::Lparen,
+                Token::Ident("a".to_string()),
+                Token::Whitespace,
+                Token::Error(LexerError::UnknownSymbol('~')),
+                Token::Whitespace,
+                Token::Int(1),
+                Token::Error(LexerError::InvalidCharInt('2')),
+                Token::Rparen,
+            ]
+        );
+        // The errors are still collected too
+        assert_eq!(lexer.error_count(), 2);
+    }
+
+    #[test]
+    fn observe_errors() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut lexer = Lexer::new("~ 0a (x $)");
+        let observed = seen.clone();
+        lexer.on_error(move |placed| observed.borrow_mut().push(placed.clone()));
+
+        lexer.read_token();
+        assert_eq!(seen.borrow().len(), 1);
+        lexer.read_token();
+        lexer.read_token();
+        assert_eq!(seen.borrow().len(), 2);
+        while lexer.read_token().token != Token::Eof {}
+
+        assert_eq!(
+            seen.borrow()
+                .iter()
+                .map(|placed| placed.token.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Token::Error(LexerError::UnknownSymbol('~')),
+                Token::Error(LexerError::InvalidCharInt('a')),
+                Token::Error(LexerError::UnknownSymbol('$')),
+            ]
+        );
+        assert_eq!(*seen.borrow(), lexer.errors);
+    }
+
+    #[test]
+    fn sort_errors() {
+        let mut lexer = Lexer::with_options(
+            "~ \"ab\\x\"",
+            LexerOptions {
+                max_string_length: Some(1),
+                ..Default::default()
+            },
+        );
+        while lexer.read_token().token != Token::Eof {}
+        // The string is found to be too long after the bad escape inside it
+        assert_eq!(lexer.errors.len(), 3);
+        assert_eq!(
+            lexer.errors[1].token,
+            Token::Error(LexerError::UnknownEscapeChar('x'))
+        );
+
+        let sorted = lexer.sorted_errors();
+        assert_eq!(sorted.len(), 3);
+        assert_eq!(
+            sorted[0].token,
+            Token::Error(LexerError::UnknownSymbol('~'))
+        );
+        assert_eq!(sorted[1].token, Token::Error(LexerError::TokenTooLong));
+        assert_eq!(sorted[1].span.start_column, 3);
+        assert_eq!(
+            sorted[2].token,
+            Token::Error(LexerError::UnknownEscapeChar('x'))
+        );
+
+        // Identical errors are only reported once
+        let duplicate = PlacedToken {
+            span: lexer.errors[0].span.clone(),
+            token: Token::Error(LexerError::UnknownSymbol('~')),
+        };
+        lexer.errors.push(duplicate);
+        assert_eq!(lexer.sorted_errors().len(), 3);
+    }
+
+    #[test]
+    fn read_overflowing_integers() {
+        let mut lexer = Lexer::new("170141183460469231731687303715884105727");
+        assert_eq!(lexer.read_token().token, Token::Int(i128::MAX));
+        assert_eq!(lexer.errors.len(), 0);
+
+        lexer = Lexer::new("170141183460469231731687303715884105728 1");
+        assert_eq!(lexer.read_token().token, Token::Int(i128::MAX));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Int(1));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IntegerOverflow)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 39
+            }
+        );
+
+        lexer = Lexer::new("u340282366920938463463374607431768211456");
+        assert_eq!(lexer.read_token().token, Token::Uint(u128::MAX));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IntegerOverflow)
+        );
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn read_big_integers() {
+        use num_bigint::{BigInt, BigUint};
+
+        let options = LexerOptions {
+            big_integers: true,
+            ..Default::default()
+        };
+        let input =
+            "1234567890123456789012345678901234567890 u1234567890123456789012345678901234567890 42";
+        let mut lexer = Lexer::with_options(input, options);
+        let token = lexer.read_token().token;
+        assert_eq!(
+            token,
+            Token::BigInt(
+                "1234567890123456789012345678901234567890"
+                    .parse::<BigInt>()
+                    .unwrap()
+            )
+        );
+        match token {
+            Token::BigInt(num) => {
+                assert_eq!(num.to_string(), "1234567890123456789012345678901234567890")
+            }
+            _ => panic!("expected a BigInt token"),
+        }
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(
+            lexer.read_token().token,
+            Token::BigUint(
+                "1234567890123456789012345678901234567890"
+                    .parse::<BigUint>()
+                    .unwrap()
+            )
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        // Literals that fit are still 128-bit tokens
+        assert_eq!(lexer.read_token().token, Token::Int(42));
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn read_char_literals() {
+        let mut lexer = Lexer::new("#\\a #\\( #\\\\n #\\\\ #\\newline #\\space");
+        assert_eq!(lexer.read_token().token, Token::Char('a'));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Char('('));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Char('\n'));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Char('\\'));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Char('\n'));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Char(' '));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert!(lexer.errors.is_empty());
+
+        let input = "#\\ab #\\";
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.read_token().token, Token::Char('�'));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Char('�'));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.errors.len(), 2);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharLiteral)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 4
+            }
+        );
+        assert_eq!(
+            lexer.errors[1].span,
+            Span {
+                start_line: 1,
+                start_column: 6,
+                end_line: 1,
+                end_column: 7
+            }
+        );
+
+        // A `#` that does not start a character literal is an attribute
+        let mut lexer = Lexer::new("#a");
+        assert_eq!(lexer.read_token().token, Token::Attribute("a".to_string()));
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn read_hash_and_attributes() {
+        let mut lexer = Lexer::new("# #[attr] #inline foo");
+        assert_eq!(lexer.read_token().token, Token::Hash);
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Attribute("attr".to_string()));
+        assert_eq!(
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 9
+            }
+        );
+        lexer.read_token();
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Attribute("inline".to_string())
+        );
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert!(lexer.errors.is_empty());
+
+        // A `#` before anything else is just a `#`
+        lexer = Lexer::new("#(a)");
+        assert_eq!(lexer.read_token().token, Token::Hash);
+        assert_eq!(lexer.read_token().token, Token::Lparen);
+
+        for input in ["#[attr x", "#[]"] {
+            let mut lexer = Lexer::new(input);
+            lexer.read_token();
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::InvalidAttribute)
+            );
+        }
+    }
+
+    #[test]
+    fn configure_identifier_starts() {
+        let mut lexer = Lexer::builder()
+            .identifier_starts(&['_', '-', '!'])
+            .build("_foo -foo !foo - foo -> !=");
+        let tokens: Vec<Token> = lexer
+            .read_tokens(14)
+            .into_iter()
+            .map(|placed| placed.token)
+            .filter(|token| !matches!(token, Token::Whitespace))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("_foo".to_string()),
+                Token::Ident("-foo".to_string()),
+                Token::Ident("!foo".to_string()),
+                Token::Minus,
+                Token::Ident("foo".to_string()),
+                Token::Arrow,
+                Token::NotEqual,
+                Token::Eof,
+            ]
+        );
+        assert!(lexer.errors.is_empty());
+
+        // By default, only letters start an identifier
+        let mut lexer = Lexer::new("_foo -foo");
+        assert_eq!(lexer.read_token().token, Token::Placeholder);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnknownSymbol('_'))
+        );
+        lexer.read_token();
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Minus);
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+    }
+
+    #[test]
+    fn read_qualified_names() {
+        use crate::lexer::token::TokenKind;
+
+        let mut lexer = Lexer::builder()
+            .qualified_names(true)
+            .build("foo.bar .bar foo . bar .a.b");
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::QualifiedName("foo.bar".to_string()));
+        assert_eq!(
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 7
+            }
+        );
+        lexer.read_token();
+        assert_eq!(
+            lexer.read_token().token,
+            Token::QualifiedName(".bar".to_string())
+        );
+        lexer.read_token();
+        // With spaces around the dot, these are still separate tokens
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Dot);
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Ident("bar".to_string()));
+        lexer.read_token();
+        assert_eq!(
+            lexer.read_token().token,
+            Token::QualifiedName(".a.b".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert!(lexer.errors.is_empty());
+
+        // Off by default
+        let kinds: Vec<TokenKind> = tokenize("foo.bar .bar")
+            .0
+            .iter()
+            .map(|placed| placed.token.kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Ident,
+                TokenKind::Dot,
+                TokenKind::Ident,
+                TokenKind::Whitespace,
+                TokenKind::Dot,
+                TokenKind::Ident,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn read_at_and_annotations() {
+        let mut lexer = Lexer::new("@ @foo @1 @(a)");
+        assert_eq!(lexer.read_token().token, Token::At);
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Annotation("foo".to_string()));
+        assert_eq!(
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 6
+            }
+        );
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::At);
+        assert_eq!(lexer.read_token().token, Token::Int(1));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::At);
+        assert_eq!(lexer.read_token().token, Token::Lparen);
+        assert!(lexer.errors.is_empty());
+
+        // Identifiers do not contain or end at an `@`
+        lexer = Lexer::new("foo @bar baz@qux");
+        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
+        lexer.read_token();
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Annotation("bar".to_string())
+        );
+        lexer.read_token();
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharIdent('@'))
+        );
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace_events() {
+        use crate::lexer::token::TokenKind;
+
+        let span = |start_column, end_column| Span {
+            start_line: 1,
+            start_column,
+            end_line: 1,
+            end_column,
+        };
+        let read_char = |ch, column| TraceEvent::ReadChar {
+            ch,
+            line: 1,
+            column,
+        };
+
+        let mut lexer = Lexer::new("(ab)");
+        lexer.read_tokens(4);
+        assert_eq!(
+            lexer.trace(),
+            &[
+                read_char('(', 1),
+                read_char('a', 2),
+                TraceEvent::ReadToken {
+                    kind: TokenKind::Lparen,
+                    span: span(1, 1),
+                },
+                read_char('b', 3),
+                read_char(')', 4),
+                TraceEvent::ReadToken {
+                    kind: TokenKind::Ident,
+                    span: span(2, 3),
+                },
+                read_char('\0', 5),
+                TraceEvent::ReadToken {
+                    kind: TokenKind::Rparen,
+                    span: span(4, 4),
+                },
+                read_char('\0', 6),
+                TraceEvent::ReadToken {
+                    kind: TokenKind::Eof,
+                    span: span(5, 5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_embedded_nul() {
+        let input = "(a\0b) \"c\0\"";
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.read_token().token, Token::Lparen);
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Placeholder);
+        assert_eq!(lexer.read_token().token, Token::Ident("b".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Rparen);
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(
+            lexer.read_token().token,
+            Token::AsciiString("c\0".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.errors.len(), 2);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::UnexpectedNul)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 3
+            }
+        );
+        assert_eq!(
+            lexer.errors[1].token,
+            Token::Error(LexerError::UnexpectedNul)
+        );
+        assert_eq!(lexer.errors[1].span.start_column, 9);
+
+        // Within UTF-8 strings and comments too
+        lexer = Lexer::new("u\"\0é\" ;; a\0b\n1");
+        assert_eq!(
+            lexer.read_token().token,
+            Token::Utf8String("\0é".to_string())
+        );
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Comment("a\0b".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Int(1));
+        assert_eq!(lexer.errors.len(), 2);
+        assert!(lexer
+            .errors
+            .iter()
+            .all(|e| e.token == Token::Error(LexerError::UnexpectedNul)));
+        assert_eq!(lexer.errors[0].span.start_column, 3);
+        assert_eq!(lexer.errors[1].span.start_column, 11);
+    }
+
+    #[test]
+    fn borrow_identifiers() {
+        let input = "(foo ubar)";
+        let mut lexer = Lexer::new(input);
+        lexer.read_token();
+        let ident = lexer.read_identifier(None);
+        assert_eq!(ident, "foo");
+        assert!(matches!(ident, Cow::Borrowed(_)));
+        assert_eq!(ident.as_ptr(), input[1..].as_ptr());
+
+        lexer.read_token();
+        lexer.read_char();
+        let ident = lexer.read_identifier(Some('u'));
+        assert_eq!(ident, "ubar");
+        assert_eq!(ident.as_ptr(), input[5..].as_ptr());
+
+        // Following multi-byte characters
+        let input = "u\"é\" baz";
+        let mut lexer = Lexer::new(input);
+        lexer.read_tokens(2);
+        assert_eq!(lexer.read_identifier(None), "baz");
+
+        // Other sources have nothing to borrow from
+        let mut lexer = Lexer::with_source("foo".chars(), LexerOptions::default());
+        let ident = lexer.read_identifier(None);
+        assert_eq!(ident, "foo");
+        assert!(matches!(ident, Cow::Owned(_)));
+    }
+
+    #[test]
+    fn read_leading_zeros() {
+        let mut lexer = Lexer::new("0123");
+        assert_eq!(lexer.read_token().token, Token::Int(123));
+        assert!(lexer.errors.is_empty());
+
+        let options = LexerOptions {
+            leading_zeros: LeadingZeros::Error,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options("0123", options);
+        assert_eq!(lexer.read_token().token, Token::Int(123));
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(lexer.errors[0].token, Token::Error(LexerError::LeadingZero));
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 1
+            }
+        );
+
+        let options = LexerOptions {
+            leading_zeros: LeadingZeros::Octal,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options("0123 089", options.clone());
+        assert_eq!(lexer.read_token().token, Token::Int(83));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharInt('8'))
+        );
+
+        // A lone zero is the same in every mode
+        let mut lexer = Lexer::with_options("0", options);
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn reject_scientific_notation() {
+        for (input, start_column, end_column) in [("1e10", 1, 4), ("1E5", 1, 3), ("u25e1", 2, 5)] {
+            let mut lexer = Lexer::new(input);
+            let token = lexer.read_token().token;
+            assert!(
+                matches!(token, Token::Int(1) | Token::Uint(25)),
+                "{}",
+                input
+            );
+            assert_eq!(lexer.read_token().token, Token::Eof);
+            assert_eq!(lexer.errors.len(), 1, "{}", input);
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::ScientificNotationUnsupported)
+            );
+            assert_eq!(lexer.errors[0].span.start_column, start_column);
+            assert_eq!(lexer.errors[0].span.end_column, end_column);
+        }
+
+        // Other characters after the digits are still invalid, and an
+        //  exponent must be directly after them
+        let (_, errors) = tokenize("1e");
+        assert_eq!(
+            errors[0].token,
+            Token::Error(LexerError::InvalidCharInt('e'))
+        );
+        let (tokens, errors) = tokenize("1 e10");
+        assert_eq!(tokens[0].token, Token::Int(1));
+        assert_eq!(tokens[2].token, Token::Ident("e10".to_string()));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn reject_malformed_numbers() {
+        for (input, token) in [
+            ("1.2.3", Token::Int(1)),
+            ("+-5", Token::Placeholder),
+            ("--5", Token::Placeholder),
+            ("-+5", Token::Placeholder),
+        ] {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(lexer.read_token().token, token, "{}", input);
+            assert_eq!(lexer.read_token().token, Token::Eof);
+            assert_eq!(lexer.errors.len(), 1, "{}", input);
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::MalformedNumber)
+            );
+            assert_eq!(
+                lexer.errors[0].span,
+                Span {
+                    start_line: 1,
+                    start_column: 1,
+                    end_line: 1,
+                    end_column: input.len() as u32
+                }
+            );
+        }
+
+        // A single sign is still its own token
+        let (tokens, errors) = tokenize("(- 5) -5 ->");
+        assert!(errors.is_empty());
+        assert_eq!(tokens[1].token, Token::Minus);
+        assert_eq!(tokens[6].token, Token::Minus);
+        assert_eq!(tokens[7].token, Token::Int(5));
+        assert_eq!(tokens[9].token, Token::Arrow);
+    }
+
+    #[test]
+    fn read_uppercase_radix_prefixes() {
+        for (upper, lower) in [
+            ("0X12", "0x12"),
+            ("0B101", "0b101"),
+            ("0O17", "0o17"),
+            ("u0B101", "u0b101"),
+            ("u0O17", "u0o17"),
+        ] {
+            let mut lexer = Lexer::new(upper);
+            assert_eq!(lexer.read_token().token, testing::first_token(lower));
+            assert_eq!(lexer.read_token().token, Token::Eof);
+            assert!(lexer.errors.is_empty());
+        }
+        assert_eq!(testing::first_token("0X12"), Token::Bytes(vec![0x12]));
+    }
+
+    #[test]
+    fn read_binary_literals() {
+        let mut lexer = Lexer::new("0b1010 u0b11 0b0");
+        assert_eq!(lexer.read_token().token, Token::Int(10));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Uint(3));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert!(lexer.errors.is_empty());
+
+        lexer = Lexer::new("0b ");
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::MissingDigits)
+        );
+        assert_eq!(
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 2
+            }
+        );
+
+        lexer = Lexer::new("0b102");
+        assert_eq!(lexer.read_token().token, Token::Int(2));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharInt('2'))
+        );
+
+        let input = format!("0b1{}", "0".repeat(127));
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(lexer.read_token().token, Token::Int(i128::MAX));
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::IntegerOverflow)
+        );
+    }
+
+    #[test]
+    fn read_octal_literals() {
+        let mut lexer = Lexer::new("0o755 u0o17");
+        assert_eq!(lexer.read_token().token, Token::Int(0o755));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Uint(15));
+        assert!(lexer.errors.is_empty());
+
+        lexer = Lexer::new("0o8");
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::InvalidCharInt('8'))
+        );
+
+        lexer = Lexer::new("0o");
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert_eq!(
+            lexer.errors[0].token,
+            Token::Error(LexerError::MissingDigits)
+        );
+
+        // Unlike a leading zero, the prefix always means octal
+        let options = LexerOptions {
+            leading_zeros: LeadingZeros::Error,
+            ..Default::default()
+        };
+        lexer = Lexer::with_options("0o10", options);
+        assert_eq!(lexer.read_token().token, Token::Int(8));
+        assert!(lexer.errors.is_empty());
+    }
+
+    #[test]
+    fn recover_in_linear_time() {
+        use std::cell::Cell;
+
+        // Fragments which each trigger recovery, one after another
+        let fragments = [
+            "0xG", "0xabcG", "12ab", "u3x", "0b12", "foo$bar", "~~", "#[x", "\"\\q", "\"",
+        ];
+        let input = (0..10_000)
+            .map(|i| fragments[i % fragments.len()])
+            .collect::<Vec<_>>()
+            .join(" ");
+        let chars = input.chars().count();
+
+        // Count every character pulled from the input
+        let reads = Cell::new(0);
+        let source = input.chars().inspect(|_| reads.set(reads.get() + 1));
+        let mut lexer = Lexer::builder().build_from_source(source);
+        let mut tokens = 0;
+        while lexer.read_token().token != Token::Eof {
+            tokens += 1;
+            assert!(tokens <= chars, "lexer is not making progress");
+        }
+
+        // Each character is read from the input once, and consumed once
+        //  (plus the end of the input, which reading `Eof` steps past)
+        assert_eq!(reads.get(), chars);
+        assert!(lexer.offset <= chars + 2);
+        assert!(lexer.errors.len() <= chars);
+    }
+
+    #[test]
+    fn measure_recovery() {
+        let mut lexer = Lexer::new("0abc 12x u5");
+        assert_eq!(lexer.last_recovery_len(), 0);
+        assert_eq!(lexer.read_token().token, Token::Int(0));
+        assert_eq!(lexer.last_recovery_len(), 3);
+        lexer.read_token();
+        lexer.read_token();
+        assert_eq!(lexer.last_recovery_len(), 1);
+        assert_eq!(lexer.total_recovery_len(), 4);
+
+        // Reading a token without an error leaves the counts alone
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Uint(5));
+        assert_eq!(lexer.last_recovery_len(), 1);
+        assert_eq!(lexer.total_recovery_len(), 4);
+    }
+
+    #[test]
+    fn count_errors() {
+        let mut lexer = Lexer::new("foo ~ 0a");
+        lexer.read_tokens(2);
+        assert!(!lexer.had_errors());
+        assert_eq!(lexer.error_count(), 0);
+
+        lexer.read_token();
+        assert!(lexer.had_errors());
+        assert_eq!(lexer.error_count(), 1);
+
+        lexer.read_tokens(3);
+        assert_eq!(lexer.error_count(), 2);
+        assert_eq!(lexer.errors().len(), 2);
+        assert_eq!(
+            lexer.errors()[1].token,
+            Token::Error(LexerError::InvalidCharInt('a'))
+        );
+    }
+
+    #[test]
+    fn remaining_input() {
+        let input = "(ab u\"é\") rest of it";
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.remaining(), input);
+        lexer.read_tokens(2);
+        assert_eq!(lexer.remaining(), " u\"é\") rest of it");
+        lexer.read_tokens(3);
+        assert_eq!(lexer.remaining(), " rest of it");
+        lexer.read_tokens(10);
+        assert_eq!(lexer.remaining(), "");
+
+        let lexer = Lexer::with_source("abc".chars(), LexerOptions::default());
+        assert_eq!(lexer.remaining(), "");
+    }
+
+    #[test]
+    fn lex_fragment_at_position() {
+        let source = "(begin\n  (ok u1) (print \"a\nb\"))";
+        let start = source.find("(ok").unwrap();
+        let mut lexer = Lexer::new_at(&source[start..], Position { line: 2, column: 3 }).unwrap();
+        let (tokens, _) = tokenize(source);
+        let expected: Vec<_> = tokens
+            .into_iter()
+            .filter(|placed| placed.span.start_line >= 2)
+            .collect();
+        let tokens = lexer.read_tokens(expected.len());
+        assert_eq!(tokens, expected);
+        assert_eq!(
+            tokens[0].span,
+            Span {
+                start_line: 2,
+                start_column: 3,
+                end_line: 2,
+                end_column: 3
+            }
+        );
+    }
+
+    #[test]
+    fn reject_invalid_start_position() {
+        let start = Position { line: 1, column: 0 };
+        let err = Lexer::new_at("a", start).err().unwrap();
+        assert_eq!(err, InvalidPosition(start));
+        assert_eq!(
+            err.to_string(),
+            "lines and columns start at 1, but lexing was started at 1:0"
+        );
+        assert!(Lexer::new_at("a", Position { line: 0, column: 1 }).is_err());
+
+        // The spans of the input must fit from the start position
+        let start = Position {
+            line: u32::MAX,
+            column: 1,
+        };
+        assert!(Lexer::new_at("a", start).is_ok());
+        assert_eq!(
+            Lexer::new_at("a\nb", start).err(),
+            Some(InvalidPosition(start))
+        );
+        let start = Position {
+            line: 1,
+            column: u32::MAX - 1,
+        };
+        assert!(Lexer::new_at("a\nbcd", start).is_ok());
+        assert!(Lexer::new_at("abc", start).is_err());
+    }
+
+    #[test]
+    fn lex_fragment_with_options() {
+        let start = Position { line: 3, column: 5 };
+        let options = LexerOptions {
+            significant_newlines: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::new_at_with_options("a\nb", start, options).unwrap();
+        assert_eq!(lexer.read_token().token, Token::Ident("a".to_string()));
+        let newline = lexer.read_token();
+        assert_eq!(newline.token, Token::Newline);
+        assert_eq!(newline.span.start_line, 3);
+        assert_eq!(newline.span.start_column, 6);
+        let b = lexer.read_token();
+        assert_eq!((b.span.start_line, b.span.start_column), (4, 1));
+    }
+
+    #[test]
+    fn attach_trailing_trivia() {
+        let mut lexer = Lexer::new("foo ;; bar\nbaz\n;; qux");
+        let (placed, trivia) = lexer.read_token_with_trailing_trivia();
+        assert_eq!(placed.token, Token::Ident("foo".to_string()));
+        let trivia: Vec<Token> = trivia.into_iter().map(|p| p.token).collect();
         assert_eq!(
-            lexer.read_token().token,
-            Token::AsciiString("open".to_string())
+            trivia,
+            vec![Token::Whitespace, Token::Comment("bar".to_string())]
         );
-        assert_eq!(lexer.errors.len(), 1);
+
+        // The line break is not trailing trivia
+        let (placed, trivia) = lexer.read_token_with_trailing_trivia();
+        assert_eq!(placed.token, Token::Whitespace);
+        assert!(trivia.is_empty());
+
+        // Nor is a comment on the next line
+        let (placed, trivia) = lexer.read_token_with_trailing_trivia();
+        assert_eq!(placed.token, Token::Ident("baz".to_string()));
+        assert!(trivia.is_empty());
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.read_token().token, Token::Comment("qux".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+    }
+
+    #[test]
+    fn read_multi_line_strings() {
+        let input = "(\"line1\nline2\" u\"a\r\nb\")";
+        let mut lexer = Lexer::new(input);
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::AsciiString("line1\nline2".to_string()));
         assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::UnterminatedString)
+            placed.span,
+            Span {
+                start_line: 1,
+                start_column: 2,
+                end_line: 2,
+                end_column: 6
+            }
         );
-
-        lexer = Lexer::new("\"👎\"");
+        lexer.read_token();
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Utf8String("a\r\nb".to_string()));
+        assert_eq!(placed.span.start_line, 2);
+        assert_eq!(placed.span.end_line, 3);
+        let placed = lexer.read_token();
+        assert_eq!(placed.token, Token::Rparen);
+        assert_eq!(placed.span.start_line, 3);
+        assert_eq!(placed.span.start_column, 3);
+        assert!(lexer.errors.is_empty());
+
+        let options = LexerOptions {
+            forbid_multiline_strings: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options(input, options);
+        lexer.read_token();
         assert_eq!(
             lexer.read_token().token,
-            Token::AsciiString("👎".to_string())
+            Token::AsciiString("line1\nline2".to_string())
         );
         assert_eq!(lexer.errors.len(), 1);
         assert_eq!(
             lexer.errors[0].token,
-            Token::Error(LexerError::IllegalCharString('👎'))
-        );
-
-        lexer = Lexer::new("\"\\u{1F600}\"");
-        assert_eq!(
-            lexer.read_token().token,
-            Token::AsciiString("�{1F600}".to_string())
+            Token::Error(LexerError::NewlineInString)
         );
-        assert_eq!(lexer.errors.len(), 1);
         assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::UnknownEscapeChar('u'))
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 8,
+                end_line: 1,
+                end_column: 8
+            }
         );
+        lexer.read_tokens(3);
+        assert_eq!(lexer.errors.len(), 2);
+    }
 
-        lexer = Lexer::new("u\"\\u{1F600}\"");
+    #[test]
+    fn forbid_raw_control_chars() {
+        let input = "\"a\tb\" u\"a\\tb\"";
+        let mut lexer = Lexer::new(input);
         assert_eq!(
             lexer.read_token().token,
-            Token::Utf8String("\\u{1F600}".to_string())
+            Token::AsciiString("a\tb".to_string())
         );
-
-        lexer = Lexer::new("u\"quote \\\"this\\\"\"");
+        lexer.read_token();
         assert_eq!(
             lexer.read_token().token,
-            Token::Utf8String("quote \"this\"".to_string())
+            Token::Utf8String("a\tb".to_string())
         );
+        assert!(lexer.errors.is_empty());
 
-        lexer = Lexer::new("u\"\\n\\r\\t\\0\\\\ ok\"");
+        let options = LexerOptions {
+            forbid_raw_control_chars: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options(input, options);
         assert_eq!(
             lexer.read_token().token,
-            Token::Utf8String("\n\r\t\0\\ ok".to_string())
+            Token::AsciiString("a\tb".to_string())
         );
-
-        lexer = Lexer::new("u\"\\x\"");
-        assert_eq!(lexer.read_token().token, Token::Utf8String("�".to_string()));
         assert_eq!(lexer.errors.len(), 1);
         assert_eq!(
             lexer.errors[0].token,
-            Token::Error(LexerError::UnknownEscapeChar('x'))
+            Token::Error(LexerError::RawControlInString)
         );
-
-        lexer = Lexer::new("u\"open");
         assert_eq!(
-            lexer.read_token().token,
-            Token::Utf8String("open".to_string())
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 3
+            }
         );
-        assert_eq!(lexer.errors.len(), 1);
+        lexer.read_token();
         assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::UnterminatedString)
+            lexer.read_token().token,
+            Token::Utf8String("a\tb".to_string())
         );
+        assert_eq!(lexer.errors.len(), 1);
+    }
 
-        lexer = Lexer::new("0x123abc");
-        if let Token::Bytes(v) = lexer.read_token().token {
-            assert_eq!(v.len(), 3);
-            assert_eq!(v[0], 0x12);
-            assert_eq!(v[1], 0x3a);
-            assert_eq!(v[2], 0xbc);
-        } else {
-            assert!(false);
+    #[test]
+    fn report_dangling_escape() {
+        for input in ["\"abc\\", "u\"abc\\"] {
+            let mut lexer = Lexer::new(input);
+            let placed = lexer.read_token();
+            assert_eq!(placed.token.utf8_byte_len(), Some(3));
+            assert_eq!(lexer.read_token().token, Token::Eof);
+            assert_eq!(lexer.errors.len(), 2);
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::DanglingEscape)
+            );
+            let end = input.len() as u32;
+            assert_eq!(
+                lexer.errors[0].span,
+                Span {
+                    start_line: 1,
+                    start_column: end,
+                    end_line: 1,
+                    end_column: end
+                }
+            );
+            assert_eq!(
+                lexer.errors[1].token,
+                Token::Error(LexerError::UnterminatedString)
+            );
         }
+    }
 
-        lexer = Lexer::new("0xdefg");
-        if let Token::Bytes(v) = lexer.read_token().token {
-            assert_eq!(v.len(), 1);
-            assert_eq!(v[0], 0xde);
-        } else {
-            assert!(false);
+    #[test]
+    fn recover_from_unknown_escapes() {
+        for (policy, ascii, utf8) in [
+            (EscapeErrorPolicy::Replace, "a�b", "é�"),
+            (EscapeErrorPolicy::Keep, "a\\xb", "é\\x"),
+            (EscapeErrorPolicy::Drop, "ab", "é"),
+        ] {
+            let mut lexer = Lexer::builder()
+                .escape_errors(policy)
+                .build("\"a\\xb\" u\"é\\x\"");
+            assert_eq!(
+                lexer.read_token().token,
+                Token::AsciiString(ascii.to_string())
+            );
+            lexer.read_token();
+            assert_eq!(
+                lexer.read_token().token,
+                Token::Utf8String(utf8.to_string())
+            );
+            assert_eq!(lexer.errors.len(), 2);
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::UnknownEscapeChar('x'))
+            );
+            assert_eq!(
+                lexer.errors[1].token,
+                Token::Error(LexerError::UnknownEscapeChar('x'))
+            );
         }
-        assert_eq!(lexer.errors.len(), 1);
-        assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::InvalidCharBuffer('g'))
-        );
+    }
 
-        lexer = Lexer::new("0xdef");
-        if let Token::Bytes(v) = lexer.read_token().token {
-            assert_eq!(v.len(), 1);
-            assert_eq!(v[0], 0xde);
-        } else {
-            assert!(false);
-        }
-        assert_eq!(lexer.errors.len(), 1);
+    #[test]
+    fn configure_replacement_char() {
+        let mut lexer = Lexer::builder()
+            .replacement_char('?')
+            .build("\"\\x\" u\"\\u{D800}\" #\\bogus");
         assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::InvalidBufferLength(3))
+            lexer.read_token().token,
+            Token::AsciiString("?".to_string())
         );
-
-        lexer = Lexer::new("0x00p5");
-        if let Token::Bytes(v) = lexer.read_token().token {
-            assert_eq!(v.len(), 1);
-            assert_eq!(v[0], 0x0);
-        } else {
-            assert!(false);
-        }
-        assert_eq!(lexer.errors.len(), 1);
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Utf8String("?".to_string()));
+        lexer.read_token();
+        assert_eq!(lexer.read_token().token, Token::Char('?'));
+        assert_eq!(lexer.errors.len(), 3);
         assert_eq!(
             lexer.errors[0].token,
-            Token::Error(LexerError::InvalidCharBuffer('p'))
+            Token::Error(LexerError::UnknownEscapeChar('x'))
         );
+    }
 
-        lexer = Lexer::new("0xdef0 ");
-        if let Token::Bytes(v) = lexer.read_token().token {
-            assert_eq!(v.len(), 2);
-            assert_eq!(v[0], 0xde);
-            assert_eq!(v[1], 0xf0);
-        } else {
-            assert!(false);
-        }
-
-        lexer = Lexer::new("foo");
-        assert_eq!(lexer.read_token().token, Token::Ident("foo".to_string()));
-
-        lexer = Lexer::new("ubar");
-        assert_eq!(lexer.read_token().token, Token::Ident("ubar".to_string()));
-
-        lexer = Lexer::new("baz👍buz");
-        assert_eq!(lexer.read_token().token, Token::Ident("baz".to_string()));
+    #[test]
+    fn reject_invalid_unicode_escapes() {
+        let mut lexer = Lexer::new("u\"\\u{D800}\"");
+        assert_eq!(lexer.read_token().token, Token::Utf8String("�".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
         assert_eq!(lexer.errors.len(), 1);
         assert_eq!(
             lexer.errors[0].token,
-            Token::Error(LexerError::InvalidCharIdent('👍'))
+            Token::Error(LexerError::InvalidUnicodeEscape)
         );
-
-        lexer = Lexer::new("+");
-        assert_eq!(lexer.read_token().token, Token::Plus);
-
-        lexer = Lexer::new("-");
-        assert_eq!(lexer.read_token().token, Token::Minus);
-
-        lexer = Lexer::new("*");
-        assert_eq!(lexer.read_token().token, Token::Multiply);
-
-        lexer = Lexer::new("/");
-        assert_eq!(lexer.read_token().token, Token::Divide);
-
-        lexer = Lexer::new("<");
-        assert_eq!(lexer.read_token().token, Token::Less);
-
-        lexer = Lexer::new("<=");
-        assert_eq!(lexer.read_token().token, Token::LessEqual);
-
-        lexer = Lexer::new(">");
-        assert_eq!(lexer.read_token().token, Token::Greater);
-
-        lexer = Lexer::new(">=");
-        assert_eq!(lexer.read_token().token, Token::GreaterEqual);
-
-        lexer = Lexer::new(";; this is a comment");
         assert_eq!(
-            lexer.read_token().token,
-            Token::Comment("this is a comment".to_string())
+            lexer.errors[0].span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 10
+            }
         );
 
-        lexer = Lexer::new(";; this is a comment\nthis is not");
-        assert_eq!(
-            lexer.read_token().token,
-            Token::Comment("this is a comment".to_string())
-        );
+        // The rest of an invalid escape is consumed, through its `}`
+        for input in ["u\"\\u{zz}\"", "u\"\\u{1234567}\""] {
+            let mut lexer = Lexer::builder().replacement_char('?').build(input);
+            assert_eq!(lexer.read_token().token, Token::Utf8String("?".to_string()));
+            assert_eq!(lexer.read_token().token, Token::Eof);
+            assert_eq!(lexer.errors.len(), 1);
+            assert_eq!(lexer.errors[0].span.end_column, input.len() as u32 - 1);
+        }
 
-        lexer = Lexer::new(";; this is a comment\r\n");
-        assert_eq!(
-            lexer.read_token().token,
-            Token::Comment("this is a comment".to_string())
-        );
+        // An escape missing its `}` stops at the closing quote
+        let mut lexer = Lexer::new("u\"\\u{zz\" a");
+        assert_eq!(lexer.read_token().token, Token::Utf8String("�".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Whitespace);
+        assert_eq!(lexer.errors.len(), 1);
 
-        lexer = Lexer::new("; this is not a comment");
+        for input in ["u\"\\u{110000}\"", "u\"\\u{}\"", "u\"\\u41\""] {
+            let mut lexer = Lexer::new(input);
+            lexer.read_token();
+            assert_eq!(
+                lexer.errors[0].token,
+                Token::Error(LexerError::InvalidUnicodeEscape)
+            );
+        }
+    }
+
+    #[test]
+    fn limit_delimiter_depth() {
+        let options = LexerOptions {
+            max_delimiter_depth: Some(2),
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options("({(({a}))}) (b)", options);
+        while lexer.read_token().token != Token::Eof {}
+        assert_eq!(lexer.error_count(), 3);
         assert_eq!(
-            lexer.read_token().token,
-            Token::Comment("this is not a comment".to_string())
+            lexer.errors()[0].token,
+            Token::Error(LexerError::MaxDepthExceeded)
         );
-        assert_eq!(lexer.errors.len(), 1);
         assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::SingleSemiColon)
+            lexer.errors()[0].span,
+            Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 1,
+                end_column: 3
+            }
         );
+        assert_eq!(lexer.errors()[1].span.start_column, 4);
+        assert_eq!(lexer.errors()[2].span.start_column, 5);
+        // The depth is back to zero after the closers
+        assert_eq!(lexer.depth, 0);
+
+        let input = "(".repeat(100);
+        let mut lexer = Lexer::new(&input);
+        while lexer.read_token().token != Token::Eof {}
+        assert!(!lexer.had_errors());
+    }
 
-        lexer = Lexer::new("~");
-        assert_eq!(lexer.read_token().token, Token::Placeholder);
-        assert_eq!(lexer.errors.len(), 1);
+    #[test]
+    fn find_resync_points() {
+        let input = "(a (b)) ;; c\n(d\n  (e)\n(f)";
+        let mut lexer = Lexer::new(input);
+        let mut resync_points = vec![];
+        loop {
+            let resync = lexer.is_resync_point();
+            let placed = lexer.read_token();
+            if resync {
+                resync_points.push((placed.span.start_line, placed.span.start_column));
+            }
+            if placed.token == Token::Eof {
+                break;
+            }
+        }
+        // The first form, the whitespace and comment after it, the unclosed
+        //  `(d` form, and the `(f)` form after it. The `(e)` form is nested.
         assert_eq!(
-            lexer.errors[0].token,
-            Token::Error(LexerError::UnknownSymbol('~'))
+            resync_points,
+            vec![(1, 1), (1, 8), (1, 9), (1, 13), (2, 1), (4, 1)]
         );
     }
 
@@ -772,6 +4964,46 @@ mod tests {
         assert_eq!(lexer.read_token().token, Token::Eof);
     }
 
+    #[test]
+    fn read_n_tokens() {
+        let mut lexer = Lexer::new("(foo");
+        let tokens = lexer.read_tokens(3);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].token, Token::Lparen);
+        assert_eq!(tokens[1].token, Token::Ident("foo".to_string()));
+        assert_eq!(tokens[2].token, Token::Eof);
+
+        lexer = Lexer::new("1 2 3");
+        assert_eq!(lexer.read_tokens(2).len(), 2);
+        assert_eq!(lexer.read_token().token, Token::Int(2));
+        let tokens = lexer.read_tokens(10);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[2].token, Token::Eof);
+    }
+
+    #[test]
+    fn check_file_span() {
+        let mut lexer = Lexer::new_in_file("foo bar", FileId(3));
+        assert_eq!(lexer.file_id(), FileId(3));
+        let tokens = lexer.read_tokens(3);
+        let span = lexer.file_span(&tokens[2].span);
+        assert_eq!(span.file_id, FileId(3));
+        assert_eq!(
+            span.span,
+            Span {
+                start_line: 1,
+                start_column: 5,
+                end_line: 1,
+                end_column: 7
+            }
+        );
+
+        // Single-file lexing uses the default file id
+        lexer = Lexer::new("foo");
+        let token = lexer.read_token();
+        assert_eq!(lexer.file_span(&token.span).file_id, FileId(0));
+    }
+
     #[test]
     fn check_span() {
         let mut lexer = Lexer::new(
@@ -1211,6 +5443,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_span_at_end_of_line() {
+        for ending in ["\n", "\r\n", ""] {
+            let input = format!("foo{}", ending);
+            let mut lexer = Lexer::new(&input);
+            let token = lexer.read_token();
+            assert_eq!(token.token, Token::Ident("foo".to_string()));
+            assert_eq!(
+                token.span,
+                Span {
+                    start_line: 1,
+                    start_column: 1,
+                    end_line: 1,
+                    end_column: 3
+                }
+            );
+
+            let input = format!("  \"a\"{}", ending);
+            let mut lexer = Lexer::new(&input);
+            lexer.read_token();
+            let token = lexer.read_token();
+            assert_eq!(token.token, Token::AsciiString("a".to_string()));
+            assert_eq!(token.span.end_line, 1);
+            assert_eq!(token.span.end_column, 5);
+
+            let input = format!("0x12{}", ending);
+            let mut lexer = Lexer::new(&input);
+            let token = lexer.read_token();
+            assert_eq!(token.span.end_line, 1);
+            assert_eq!(token.span.end_column, 4);
+
+            let input = format!(";; c{}", ending);
+            let mut lexer = Lexer::new(&input);
+            let token = lexer.read_token();
+            assert_eq!(token.token, Token::Comment("c".to_string()));
+            assert_eq!(token.span.end_line, 1);
+            assert_eq!(token.span.end_column, 4);
+
+            // Any line ending is left for the following whitespace token
+            let token = lexer.read_token();
+            if ending.is_empty() {
+                assert_eq!(token.token, Token::Eof);
+            } else {
+                assert_eq!(token.token, Token::Whitespace);
+                assert_eq!(token.span.start_column, 5);
+                assert_eq!(token.span.end_column, 4 + ending.len() as u32);
+            }
+        }
+    }
+
+    #[test]
+    fn check_comment_span() {
+        for ending in ["\n", "\r\n"] {
+            let input = format!("(a) ;; comment{}foo", ending);
+            let mut lexer = Lexer::new(&input);
+            let tokens = lexer.read_tokens(7);
+            assert_eq!(tokens[4].token, Token::Comment("comment".to_string()));
+            assert_eq!(
+                tokens[4].span,
+                Span {
+                    start_line: 1,
+                    start_column: 5,
+                    end_line: 1,
+                    end_column: 14
+                }
+            );
+            assert_eq!(tokens[5].token, Token::Whitespace);
+            assert_eq!(
+                tokens[5].span,
+                Span {
+                    start_line: 1,
+                    start_column: 15,
+                    end_line: 1,
+                    end_column: 14 + ending.len() as u32
+                }
+            );
+            assert_eq!(tokens[6].token, Token::Ident("foo".to_string()));
+            assert_eq!(
+                tokens[6].span,
+                Span {
+                    start_line: 2,
+                    start_column: 1,
+                    end_line: 2,
+                    end_column: 3
+                }
+            );
+        }
+    }
+
     #[test]
     fn check_error_span() {
         let mut lexer = Lexer::new("0a 123");
@@ -1317,7 +5638,7 @@ mod tests {
                 start_line: 1,
                 start_column: 1,
                 end_line: 1,
-                end_column: 7
+                end_column: 6
             }
         );
 
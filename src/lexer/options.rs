@@ -0,0 +1,405 @@
+use std::collections::HashSet;
+
+use super::source::CharSource;
+use super::token::FileId;
+use super::Lexer;
+
+/// How to read an integer literal with a leading zero, like `0123`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeadingZeros {
+    /// Read it as a decimal number, ignoring the leading zeros.
+    #[default]
+    Ignore,
+    /// Read it as a decimal number, but report a `LeadingZero` error.
+    Error,
+    /// Read it as an octal number, so that `0123` is 83.
+    Octal,
+}
+
+/// What to put in a string in place of an unknown escape, like `\x`. The
+/// escape is reported as `UnknownEscapeChar` either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EscapeErrorPolicy {
+    /// Replace it with `LexerOptions::replacement_char`.
+    #[default]
+    Replace,
+    /// Keep the backslash and the character after it.
+    Keep,
+    /// Leave it out.
+    Drop,
+}
+
+/// What a column in a span counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnMode {
+    /// Each Unicode scalar value (`char`) is one column.
+    #[default]
+    Codepoints,
+    /// Each extended grapheme cluster is one column, so that `e` followed
+    /// by a combining accent, or a flag emoji, is a single column. Spans
+    /// counted this way cannot be used with `Span::byte_range`.
+    #[cfg(feature = "graphemes")]
+    Graphemes,
+}
+
+/// The prefixes which start a line comment. Defaults to just `;;`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommentPrefixes(pub Vec<String>);
+
+impl Default for CommentPrefixes {
+    fn default() -> Self {
+        CommentPrefixes(vec![";;".to_string()])
+    }
+}
+
+/// The longest identifier allowed in Clarity.
+pub const MAX_IDENTIFIER_LENGTH: usize = 128;
+
+/// Configuration for optional lexer behaviors. The defaults match the
+/// behavior of `Lexer::new`; anything else is set through `LexerBuilder`.
+#[derive(Debug, Clone)]
+pub struct LexerOptions {
+    /// Allow whitespace between the byte pairs of a buffer literal, so that
+    /// `0x de ad` is read as two bytes. Whitespace is only skipped when it is
+    /// followed by a full byte pair, so a buffer is never joined with a
+    /// following token that is not a byte.
+    pub(crate) hex_whitespace: bool,
+    /// Consume unknown symbols silently (still reporting an
+    /// `UnknownSymbol` error) instead of returning a `Token::Placeholder`.
+    pub(crate) skip_unknown_symbols: bool,
+    /// Report raw control characters (such as a literal tab) in the body of
+    /// an ASCII string as `IllegalCharString`. Escapes like `\t` are allowed.
+    pub(crate) strict_ascii: bool,
+    /// Report an advisory `KeywordShadow` warning for identifiers spelled
+    /// the same as a keyword.
+    pub(crate) warn_keyword_shadow: bool,
+    /// Report an advisory `MixedIndentation` warning for a line indented
+    /// with both tabs and spaces.
+    pub(crate) warn_mixed_indentation: bool,
+    /// Keep the whitespace between `;;` and the text of a comment, rather
+    /// than trimming it.
+    pub(crate) verbatim_comments: bool,
+    /// End an unterminated string at the end of its line, rather than at the
+    /// end of the input, so lexing can resume on the next line. String
+    /// literals cannot span multiple lines in this mode.
+    pub(crate) string_line_recovery: bool,
+    /// Also return errors from `read_token`, as `Token::Error` tokens in
+    /// the order they appear in the input, in place of any `Placeholder`.
+    pub(crate) inline_errors: bool,
+    /// Report adjacent errors of the same kind within a token as a single
+    /// error covering all of them. A run of unknown symbols is read as one
+    /// `Placeholder`, and a run of different ones is reported as a
+    /// `GarbageRun`.
+    pub(crate) collapse_errors: bool,
+    /// Report a line break in the body of a string as `NewlineInString`. The
+    /// string still continues onto the next line.
+    pub(crate) forbid_multiline_strings: bool,
+    /// Report a raw tab, carriage return or line feed in the body of a
+    /// string as `RawControlInString`, requiring the `\t`, `\r` and `\n`
+    /// escapes instead.
+    pub(crate) forbid_raw_control_chars: bool,
+    /// Read `=<` and `=>` as the `<=` and `>=` they were likely meant to be,
+    /// reporting them as a `LikelyTypo`. Otherwise they are read as `=`
+    /// followed by `<` or `>`.
+    pub(crate) detect_operator_typos: bool,
+    /// The maximum length of an identifier, which defaults to Clarity's
+    /// limit of `MAX_IDENTIFIER_LENGTH`. Longer identifiers, qualified
+    /// names, attributes and annotations are reported as `IdentifierTooLong`
+    /// and truncated.
+    pub(crate) max_identifier_length: Option<usize>,
+    /// The maximum length in bytes of a string's decoded value. Longer
+    /// strings are reported as `TokenTooLong` and truncated.
+    pub(crate) max_string_length: Option<usize>,
+    /// The maximum number of bytes in a buffer. Longer buffers are reported
+    /// as `BufferTooLong` and truncated.
+    pub(crate) max_buffer_length: Option<usize>,
+    /// The maximum nesting of `(` and `{`. Openers past it are reported as
+    /// `MaxDepthExceeded`.
+    pub(crate) max_delimiter_depth: Option<usize>,
+    /// The identifiers classified as `TokenCategory::Builtin` by
+    /// `Lexer::category`, or `None` to not classify builtins. `BUILTINS`
+    /// has the language's built-in variables.
+    pub(crate) builtins: Option<HashSet<String>>,
+    /// The identifiers classified as `TokenCategory::Type` by
+    /// `Lexer::category`, or `None` to not classify type names. `TYPE_NAMES`
+    /// has the language's types.
+    pub(crate) type_names: Option<HashSet<String>>,
+    /// How to read integer literals with a leading zero.
+    pub(crate) leading_zeros: LeadingZeros,
+    /// The prefixes which start a line comment. When one of them starts
+    /// with `;`, a lone `;` is read as a comment and reported as
+    /// `SingleSemiColon`.
+    pub(crate) comment_prefixes: CommentPrefixes,
+    /// Read each line ending (`\n` or `\r\n`) as a `Newline` token, for
+    /// dialects where a newline ends a statement, rather than as part of
+    /// the surrounding whitespace.
+    pub(crate) significant_newlines: bool,
+    /// Read a run of whitespace which reaches the start of a line, so that
+    /// the token after it is the first on its line, as `Indentation` rather
+    /// than `Whitespace`, for formatters.
+    pub(crate) mark_indentation: bool,
+    /// Characters other than letters which may start an identifier: any
+    /// of `_`, `-`, `!` and `?`. `-`, `!` and `?` only start an identifier
+    /// when a letter or `_` follows directly, so `-foo` is an identifier
+    /// but `- foo` and `->` are not.
+    pub(crate) identifier_starts: Vec<char>,
+    /// Read a dotted name with no spaces around its dots, like Clarity's
+    /// `contract.function` or `.contract`, as a single `QualifiedName`
+    /// rather than identifiers separated by `Dot`s.
+    pub(crate) qualified_names: bool,
+    /// How to recover from an unknown escape in a string.
+    pub(crate) escape_errors: EscapeErrorPolicy,
+    /// The character put in place of an invalid escape in a string, or an
+    /// invalid character literal, which defaults to `'\u{FFFD}'`.
+    pub(crate) replacement_char: char,
+    /// What the columns in spans count.
+    pub(crate) column_mode: ColumnMode,
+    /// Read integer literals that do not fit in 128 bits as `Token::BigInt`
+    /// or `Token::BigUint`, rather than reporting `IntegerOverflow`.
+    #[cfg(feature = "bignum")]
+    pub(crate) big_integers: bool,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        Self {
+            hex_whitespace: false,
+            skip_unknown_symbols: false,
+            strict_ascii: false,
+            warn_keyword_shadow: false,
+            warn_mixed_indentation: false,
+            verbatim_comments: false,
+            string_line_recovery: false,
+            inline_errors: false,
+            collapse_errors: false,
+            forbid_multiline_strings: false,
+            forbid_raw_control_chars: false,
+            detect_operator_typos: false,
+            max_identifier_length: Some(MAX_IDENTIFIER_LENGTH),
+            max_string_length: None,
+            max_buffer_length: None,
+            max_delimiter_depth: None,
+            builtins: None,
+            type_names: None,
+            leading_zeros: LeadingZeros::default(),
+            comment_prefixes: CommentPrefixes::default(),
+            significant_newlines: false,
+            mark_indentation: false,
+            identifier_starts: vec![],
+            qualified_names: false,
+            escape_errors: EscapeErrorPolicy::default(),
+            replacement_char: char::REPLACEMENT_CHARACTER,
+            column_mode: ColumnMode::default(),
+            #[cfg(feature = "bignum")]
+            big_integers: false,
+        }
+    }
+}
+
+/// Builds a `Lexer` with non-default options.
+///
+/// ```
+/// use parser2::lexer::options::LexerBuilder;
+///
+/// let mut lexer = LexerBuilder::new().strict_ascii(true).build("\"hello\"");
+/// lexer.read_token();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LexerBuilder {
+    options: LexerOptions,
+    file_id: FileId,
+}
+
+impl LexerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hex_whitespace(mut self, enabled: bool) -> Self {
+        self.options.hex_whitespace = enabled;
+        self
+    }
+
+    pub fn skip_unknown_symbols(mut self, enabled: bool) -> Self {
+        self.options.skip_unknown_symbols = enabled;
+        self
+    }
+
+    pub fn strict_ascii(mut self, enabled: bool) -> Self {
+        self.options.strict_ascii = enabled;
+        self
+    }
+
+    pub fn warn_keyword_shadow(mut self, enabled: bool) -> Self {
+        self.options.warn_keyword_shadow = enabled;
+        self
+    }
+
+    pub fn warn_mixed_indentation(mut self, enabled: bool) -> Self {
+        self.options.warn_mixed_indentation = enabled;
+        self
+    }
+
+    pub fn verbatim_comments(mut self, enabled: bool) -> Self {
+        self.options.verbatim_comments = enabled;
+        self
+    }
+
+    pub fn string_line_recovery(mut self, enabled: bool) -> Self {
+        self.options.string_line_recovery = enabled;
+        self
+    }
+
+    pub fn inline_errors(mut self, enabled: bool) -> Self {
+        self.options.inline_errors = enabled;
+        self
+    }
+
+    pub fn collapse_errors(mut self, enabled: bool) -> Self {
+        self.options.collapse_errors = enabled;
+        self
+    }
+
+    pub fn forbid_multiline_strings(mut self, enabled: bool) -> Self {
+        self.options.forbid_multiline_strings = enabled;
+        self
+    }
+
+    pub fn forbid_raw_control_chars(mut self, enabled: bool) -> Self {
+        self.options.forbid_raw_control_chars = enabled;
+        self
+    }
+
+    pub fn detect_operator_typos(mut self, enabled: bool) -> Self {
+        self.options.detect_operator_typos = enabled;
+        self
+    }
+
+    pub fn max_identifier_length(mut self, max: Option<usize>) -> Self {
+        self.options.max_identifier_length = max;
+        self
+    }
+
+    pub fn max_string_length(mut self, max: Option<usize>) -> Self {
+        self.options.max_string_length = max;
+        self
+    }
+
+    pub fn max_buffer_length(mut self, max: Option<usize>) -> Self {
+        self.options.max_buffer_length = max;
+        self
+    }
+
+    pub fn max_delimiter_depth(mut self, max: Option<usize>) -> Self {
+        self.options.max_delimiter_depth = max;
+        self
+    }
+
+    pub fn builtins(mut self, names: &[&str]) -> Self {
+        self.options.builtins = Some(names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    pub fn type_names(mut self, names: &[&str]) -> Self {
+        self.options.type_names = Some(names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    pub fn leading_zeros(mut self, mode: LeadingZeros) -> Self {
+        self.options.leading_zeros = mode;
+        self
+    }
+
+    pub fn comment_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.options.comment_prefixes =
+            CommentPrefixes(prefixes.iter().map(|prefix| prefix.to_string()).collect());
+        self
+    }
+
+    pub fn significant_newlines(mut self, enabled: bool) -> Self {
+        self.options.significant_newlines = enabled;
+        self
+    }
+
+    pub fn mark_indentation(mut self, enabled: bool) -> Self {
+        self.options.mark_indentation = enabled;
+        self
+    }
+
+    pub fn identifier_starts(mut self, starts: &[char]) -> Self {
+        self.options.identifier_starts = starts.to_vec();
+        self
+    }
+
+    pub fn qualified_names(mut self, enabled: bool) -> Self {
+        self.options.qualified_names = enabled;
+        self
+    }
+
+    pub fn escape_errors(mut self, policy: EscapeErrorPolicy) -> Self {
+        self.options.escape_errors = policy;
+        self
+    }
+
+    pub fn replacement_char(mut self, ch: char) -> Self {
+        self.options.replacement_char = ch;
+        self
+    }
+
+    pub fn column_mode(mut self, mode: ColumnMode) -> Self {
+        self.options.column_mode = mode;
+        self
+    }
+
+    #[cfg(feature = "bignum")]
+    pub fn big_integers(mut self, enabled: bool) -> Self {
+        self.options.big_integers = enabled;
+        self
+    }
+
+    /// The file being lexed, for lexers over multiple files.
+    pub fn file_id(mut self, file_id: FileId) -> Self {
+        self.file_id = file_id;
+        self
+    }
+
+    pub fn build(self, input: &str) -> Lexer<'_> {
+        let mut lexer = Lexer::with_options(input, self.options);
+        lexer.file_id = self.file_id;
+        lexer
+    }
+
+    pub fn build_from_source<'a>(self, source: impl CharSource + 'a) -> Lexer<'a> {
+        let mut lexer = Lexer::with_source(source, self.options);
+        lexer.file_id = self.file_id;
+        lexer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::error::LexerError;
+    use crate::lexer::token::Token;
+
+    #[test]
+    fn build_lexer() {
+        let mut lexer = LexerBuilder::new()
+            .skip_unknown_symbols(true)
+            .max_identifier_length(Some(3))
+            .file_id(FileId(7))
+            .build("~abcd");
+        assert_eq!(lexer.read_token().token, Token::Ident("abc".to_string()));
+        assert_eq!(lexer.read_token().token, Token::Eof);
+        assert_eq!(lexer.file_id(), FileId(7));
+        assert_eq!(
+            lexer.sorted_errors()[1].token,
+            Token::Error(LexerError::IdentifierTooLong)
+        );
+
+        // The defaults match `Lexer::new`
+        lexer = LexerBuilder::new().build("~");
+        assert_eq!(lexer.read_token().token, Token::Placeholder);
+        assert_eq!(lexer.file_id(), FileId(0));
+    }
+}
@@ -0,0 +1,113 @@
+/// A source of characters for the lexer. Implementing this allows input to
+/// be spliced in while lexing (e.g. expanding includes). Spans are reported
+/// in terms of the characters produced by the source, as if it had produced
+/// a single string.
+///
+/// Any iterator of `char`s is a source, so a rope or other chunked buffer
+/// can pass its character iterator directly.
+pub trait CharSource {
+    /// Return the next character, or `None` at the end of the input.
+    fn next_char(&mut self) -> Option<char>;
+}
+
+impl<I: Iterator<Item = char>> CharSource for I {
+    fn next_char(&mut self) -> Option<char> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::options::LexerOptions;
+    use crate::lexer::token::{Span, Token};
+    use crate::lexer::Lexer;
+    use std::str::Chars;
+
+    // Reads each segment in turn, as if they were concatenated.
+    struct Segments<'a> {
+        segments: std::slice::Iter<'a, &'a str>,
+        current: Chars<'a>,
+    }
+
+    impl<'a> Segments<'a> {
+        fn new(segments: &'a [&'a str]) -> Self {
+            Self {
+                segments: segments.iter(),
+                current: "".chars(),
+            }
+        }
+    }
+
+    impl CharSource for Segments<'_> {
+        fn next_char(&mut self) -> Option<char> {
+            loop {
+                if let Some(ch) = self.current.next() {
+                    return Some(ch);
+                }
+                self.current = self.segments.next()?.chars();
+            }
+        }
+    }
+
+    #[test]
+    fn lex_from_custom_source() {
+        let segments = ["(fo", "o\n  bar)"];
+        let mut lexer = Lexer::with_source(Segments::new(&segments), LexerOptions::default());
+        let mut expected = Lexer::new("(foo\n  bar)");
+        loop {
+            let token = lexer.read_token();
+            let expected_token = expected.read_token();
+            assert_eq!(token.token, expected_token.token);
+            assert_eq!(token.span, expected_token.span);
+            if token.token == Token::Eof {
+                break;
+            }
+        }
+
+        let mut lexer = Lexer::with_source(Segments::new(&segments), LexerOptions::default());
+        let tokens = lexer.read_tokens(5);
+        assert_eq!(tokens[1].token, Token::Ident("foo".to_string()));
+        assert_eq!(tokens[3].token, Token::Ident("bar".to_string()));
+        assert_eq!(
+            tokens[3].span,
+            Span {
+                start_line: 2,
+                start_column: 3,
+                end_line: 2,
+                end_column: 5
+            }
+        );
+    }
+
+    // A rope-like buffer of chunks, read through a plain char iterator
+    struct Chunks {
+        chunks: Vec<String>,
+    }
+
+    impl Chunks {
+        fn chars(&self) -> impl Iterator<Item = char> + '_ {
+            self.chunks.iter().flat_map(|chunk| chunk.chars())
+        }
+    }
+
+    #[test]
+    fn lex_from_char_iterator() {
+        let input = "(define-data-var n uint u0)\n;; é\n(foo \"bar\")";
+        let rope = Chunks {
+            chunks: input
+                .split_inclusive(' ')
+                .map(|chunk| chunk.to_string())
+                .collect(),
+        };
+        let mut lexer = Lexer::builder().build_from_source(rope.chars());
+        let mut expected = Lexer::new(input);
+        loop {
+            let token = lexer.read_token();
+            assert_eq!(token, expected.read_token());
+            if token.token == Token::Eof {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// A lexed input which can be searched by position, for editor features like
+//  finding the token under the cursor.
+
+use std::ops::Range;
+
+use super::options::LexerOptions;
+use super::token::{PlacedToken, Position, Token};
+use super::Lexer;
+
+/// All of the tokens of an input, along with the range of bytes each covers.
+#[derive(Debug)]
+pub struct TokenStream {
+    tokens: Vec<PlacedToken>,
+    ranges: Vec<Range<usize>>,
+}
+
+impl TokenStream {
+    /// Lex all of `input`. The final token is always `Eof`.
+    pub fn new(input: &str) -> Self {
+        let mut lexer = Lexer::new(input);
+        let mut tokens = vec![];
+        let mut ranges = vec![];
+        loop {
+            let start = lexer.byte_offset.min(input.len());
+            let token = lexer.read_token();
+            let eof = token.token == Token::Eof;
+            ranges.push(start..lexer.byte_offset.min(input.len()));
+            tokens.push(token);
+            if eof {
+                return TokenStream { tokens, ranges };
+            }
+        }
+    }
+
+    pub fn tokens(&self) -> &[PlacedToken] {
+        &self.tokens
+    }
+
+    /// The bytes of the input covered by the `index`th token.
+    pub fn range(&self, index: usize) -> Range<usize> {
+        self.ranges[index].clone()
+    }
+
+    /// The token containing the byte at `offset`, if any. Whitespace and
+    /// comments are tokens too, so this is only `None` at the end of the
+    /// input.
+    pub fn token_at(&self, offset: usize) -> Option<&PlacedToken> {
+        let index = self.ranges.partition_point(|range| range.end <= offset);
+        self.ranges
+            .get(index)
+            .filter(|range| range.contains(&offset))
+            .map(|_| &self.tokens[index])
+    }
+
+    /// The last token which ends at or before `offset`. When `offset` is
+    /// between two tokens, this is the one before it; when it is inside a
+    /// token, this is the token before that one.
+    pub fn token_before(&self, offset: usize) -> Option<&PlacedToken> {
+        let index = self
+            .ranges
+            .partition_point(|range| range.end <= offset && !range.is_empty());
+        index.checked_sub(1).map(|index| &self.tokens[index])
+    }
+}
+
+/// The token containing the byte at `offset` in `source`, without lexing
+/// all of it: lexing starts at the beginning of the line containing
+/// `offset`, and stops at the token covering it. A string or comment which
+/// starts on an earlier line is not seen as such. This is `None` at the end
+/// of the input.
+pub fn token_at_offset(source: &str, offset: usize) -> Option<PlacedToken> {
+    token_at_offset_with_options(source, offset, LexerOptions::default())
+}
+
+/// Like `token_at_offset`, lexing with the given options.
+pub fn token_at_offset_with_options(
+    source: &str,
+    offset: usize,
+    options: LexerOptions,
+) -> Option<PlacedToken> {
+    if offset >= source.len() {
+        return None;
+    }
+    // The start of the character containing `offset`
+    let char_start = (0..=offset).rev().find(|&i| source.is_char_boundary(i))?;
+    let line_start = source[..char_start].rfind('\n').map_or(0, |i| i + 1);
+    let line = source[..line_start].matches('\n').count() + 1;
+    let start = Position {
+        line: line as u32,
+        column: 1,
+    };
+    let mut lexer = Lexer::new_at_with_options(&source[line_start..], start, options).ok()?;
+    loop {
+        let token = lexer.read_token();
+        if token.token == Token::Eof {
+            return None;
+        }
+        if line_start + lexer.byte_offset > offset {
+            return Some(token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_tokens_by_offset() {
+        let stream = TokenStream::new("(foo  bar)");
+        let ident = |name: &str| Token::Ident(name.to_string());
+
+        // Inside an identifier
+        assert_eq!(stream.token_at(2).unwrap().token, ident("foo"));
+        assert_eq!(stream.token_before(2).unwrap().token, Token::Lparen);
+
+        // At the boundary between two tokens
+        assert_eq!(stream.token_at(1).unwrap().token, ident("foo"));
+        assert_eq!(stream.token_before(1).unwrap().token, Token::Lparen);
+        assert_eq!(stream.token_at(4).unwrap().token, Token::Whitespace);
+        assert_eq!(stream.token_before(4).unwrap().token, ident("foo"));
+
+        // In whitespace
+        assert_eq!(stream.token_at(5).unwrap().token, Token::Whitespace);
+        assert_eq!(stream.token_before(5).unwrap().token, ident("foo"));
+
+        // At either end of the input
+        assert_eq!(stream.token_before(0), None);
+        assert_eq!(stream.token_at(0).unwrap().token, Token::Lparen);
+        assert_eq!(stream.token_at(10), None);
+        assert_eq!(stream.token_before(10).unwrap().token, Token::Rparen);
+        assert_eq!(stream.range(3), 6..9);
+    }
+
+    #[test]
+    fn lex_token_at_offset() {
+        let source = "(define-data-var a int 1)\n\n(print   counter)\n";
+        let offset = source.find("counter").unwrap() + 3;
+        let placed = token_at_offset(source, offset).unwrap();
+        assert_eq!(placed.token, Token::Ident("counter".to_string()));
+        assert_eq!(
+            placed.span,
+            crate::lexer::token::Span {
+                start_line: 3,
+                start_column: 10,
+                end_line: 3,
+                end_column: 16
+            }
+        );
+        assert_eq!(
+            token_at_offset(source, offset),
+            TokenStream::new(source).token_at(offset).cloned()
+        );
+
+        assert_eq!(token_at_offset(source, 0).unwrap().token, Token::Lparen);
+        assert_eq!(token_at_offset(source, source.len()), None);
+
+        // The options are used to re-lex the line
+        let options = LexerOptions {
+            significant_newlines: true,
+            ..Default::default()
+        };
+        let offset = source.find('\n').unwrap();
+        assert_eq!(
+            token_at_offset(source, offset).unwrap().token,
+            Token::Whitespace
+        );
+        assert_eq!(
+            token_at_offset_with_options(source, offset, options)
+                .unwrap()
+                .token,
+            Token::Newline
+        );
+    }
+}
@@ -0,0 +1,191 @@
+// Helpers to cut down on boilerplate when writing lexer tests.
+
+use super::token::{PlacedToken, Span, Token, TokenKind};
+use super::Lexer;
+
+/// Shorthands for building expected tokens.
+impl Token {
+    pub fn ident(name: &str) -> Token {
+        Token::Ident(name.to_string())
+    }
+
+    pub fn ascii(s: &str) -> Token {
+        Token::AsciiString(s.to_string())
+    }
+
+    pub fn utf8(s: &str) -> Token {
+        Token::Utf8String(s.to_string())
+    }
+
+    pub fn bytes(bytes: &[u8]) -> Token {
+        Token::Bytes(bytes.to_vec())
+    }
+
+    pub fn comment(text: &str) -> Token {
+        Token::Comment(text.to_string())
+    }
+}
+
+/// An identifier, the most common token with a payload.
+impl From<&str> for Token {
+    fn from(name: &str) -> Token {
+        Token::ident(name)
+    }
+}
+
+/// Lex the first token of `input`, asserting that no errors were reported.
+pub fn first_token(input: &str) -> Token {
+    first_placed(input).token
+}
+
+/// Lex the first token of `input` along with its span, asserting that no
+/// errors were reported.
+pub fn first_placed(input: &str) -> PlacedToken {
+    let (placed, errors) = first_placed_with_errors(input);
+    assert!(
+        errors.is_empty(),
+        "unexpected lexer errors for {:?}: {:?}",
+        input,
+        errors
+    );
+    placed
+}
+
+/// Lex the first token of `input`, returning it with any errors reported
+/// while reading it.
+pub fn first_placed_with_errors(input: &str) -> (PlacedToken, Vec<PlacedToken>) {
+    let mut lexer = Lexer::new(input);
+    let placed = lexer.read_token();
+    (placed, lexer.errors)
+}
+
+/// Where a token stream differs from the expected kinds. See `assert_kinds`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// The position of the token in the stream.
+    pub index: usize,
+    /// The kind expected at `index`, or `None` if the input has more tokens
+    /// than expected.
+    pub expected: Option<TokenKind>,
+    pub found: TokenKind,
+    pub span: Span,
+}
+
+/// Check that `input` lexes to exactly the kinds in `expected`, including
+/// whitespace and comments, followed by the end of the input. The final
+/// `Eof` need not be listed. Errors reported along the way are not checked.
+pub fn assert_kinds(input: &str, expected: &[TokenKind]) -> Result<(), Mismatch> {
+    let mut lexer = Lexer::new(input);
+    let mut index = 0;
+    loop {
+        let placed = lexer.read_token();
+        let found = placed.token.kind();
+        let expected = expected.get(index).copied();
+        match expected {
+            None if found == TokenKind::Eof => return Ok(()),
+            Some(kind) if kind == found => index += 1,
+            _ => {
+                return Err(Mismatch {
+                    index,
+                    expected,
+                    found,
+                    span: placed.span,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::error::LexerError;
+
+    #[test]
+    fn first_token_helpers() {
+        assert_eq!(first_token("foo bar"), Token::Ident("foo".to_string()));
+        assert_eq!(first_token("u42"), Token::Uint(42));
+        assert_eq!(
+            first_placed("<=").span,
+            Span {
+                start_line: 1,
+                start_column: 1,
+                end_line: 1,
+                end_column: 2
+            }
+        );
+
+        let (placed, errors) = first_placed_with_errors("~");
+        assert_eq!(placed.token, Token::Placeholder);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].token,
+            Token::Error(LexerError::UnknownSymbol('~'))
+        );
+    }
+
+    #[test]
+    fn token_constructors() {
+        assert_eq!(first_token("foo"), Token::ident("foo"));
+        assert_eq!(first_token("foo"), "foo".into());
+        assert_eq!(first_token("\"hi\""), Token::ascii("hi"));
+        assert_eq!(first_token("u\"hé\""), Token::utf8("hé"));
+        assert_eq!(first_token("0xbeef"), Token::bytes(&[0xbe, 0xef]));
+        assert_eq!(first_token(";; note"), Token::comment("note"));
+    }
+
+    #[test]
+    fn match_token_kinds() {
+        use TokenKind::*;
+
+        assert_eq!(
+            assert_kinds("(foo u1)", &[Lparen, Ident, Whitespace, Uint, Rparen]),
+            Ok(())
+        );
+        assert_eq!(
+            assert_kinds(
+                "(foo u1) ",
+                &[Lparen, Ident, Whitespace, Uint, Rparen, Whitespace, Eof]
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn report_kind_mismatches() {
+        use TokenKind::*;
+
+        assert_eq!(
+            assert_kinds("(foo 1)", &[Lparen, Ident, Whitespace, Uint, Rparen]),
+            Err(Mismatch {
+                index: 3,
+                expected: Some(Uint),
+                found: Int,
+                span: Span {
+                    start_line: 1,
+                    start_column: 6,
+                    end_line: 1,
+                    end_column: 6
+                }
+            })
+        );
+
+        // The input runs out early
+        let mismatch = assert_kinds("(foo", &[Lparen, Ident, Rparen]).unwrap_err();
+        assert_eq!(mismatch.index, 2);
+        assert_eq!(mismatch.expected, Some(Rparen));
+        assert_eq!(mismatch.found, Eof);
+
+        // The input has more tokens than expected
+        let mismatch = assert_kinds("(foo)", &[Lparen, Ident]).unwrap_err();
+        assert_eq!(mismatch.index, 2);
+        assert_eq!(mismatch.expected, None);
+        assert_eq!(mismatch.found, Rparen);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected lexer errors")]
+    fn first_token_rejects_errors() {
+        first_token("0a");
+    }
+}
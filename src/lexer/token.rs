@@ -1,9 +1,13 @@
 use super::error::LexerError;
+use std::ops::Range;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Token {
     Eof,
     Whitespace,
+    Indentation, // whitespace reaching the start of a line, if marked
+    Newline,
     Lparen,
     Rparen,
     Lbrace,
@@ -11,26 +15,378 @@ pub enum Token {
     Colon,
     Comma,
     Dot,
+    DotDot,
+    Ellipsis,
     Int(i128),
     Uint(u128),
+    #[cfg(feature = "bignum")]
+    BigInt(num_bigint::BigInt),
+    #[cfg(feature = "bignum")]
+    BigUint(num_bigint::BigUint),
     AsciiString(String),
     Utf8String(String),
     Bytes(Vec<u8>),
+    Char(char),
     Ident(String),
+    QualifiedName(String),
     Plus,
     Minus,
     Multiply,
     Divide,
+    Percent,
     Less,
     LessEqual,
     Greater,
     GreaterEqual,
+    Equal,
+    NotEqual,
+    Arrow,
+    DoubleColon,
+    Bang,
+    Question,
+    Quote,
+    Hash,
+    At,
+    Attribute(String),
+    Annotation(String),
     Comment(String),
     Error(LexerError),
     Placeholder, // used to continue parsing after errors
 }
 
+/// The kind of a `Token`, without its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Eof,
+    Whitespace,
+    Indentation,
+    Newline,
+    Lparen,
+    Rparen,
+    Lbrace,
+    Rbrace,
+    Colon,
+    Comma,
+    Dot,
+    DotDot,
+    Ellipsis,
+    Int,
+    Uint,
+    #[cfg(feature = "bignum")]
+    BigInt,
+    #[cfg(feature = "bignum")]
+    BigUint,
+    AsciiString,
+    Utf8String,
+    Bytes,
+    Char,
+    Ident,
+    QualifiedName,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Percent,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Arrow,
+    DoubleColon,
+    Bang,
+    Question,
+    Quote,
+    Hash,
+    At,
+    Attribute,
+    Annotation,
+    Comment,
+    Error,
+    Placeholder,
+}
+
+/// Identifiers with a reserved meaning in the language.
+pub const KEYWORDS: &[&str] = &["true", "false", "none"];
+
+/// The built-in variables of the language. These are ordinary identifiers,
+/// but can be classified as `TokenCategory::Builtin`.
+pub const BUILTINS: &[&str] = &[
+    "tx-sender",
+    "tx-sponsor?",
+    "contract-caller",
+    "block-height",
+    "burn-block-height",
+    "stacks-block-height",
+    "tenure-height",
+    "chain-id",
+    "is-in-mainnet",
+    "is-in-regtest",
+    "stx-liquid-supply",
+];
+
+/// The names of the language's types, as written in type positions (e.g.
+/// `(define-data-var count uint u0)`). These are ordinary identifiers, but
+/// can be classified as `TokenCategory::Type`.
+pub const TYPE_NAMES: &[&str] = &[
+    "int",
+    "uint",
+    "bool",
+    "principal",
+    "buff",
+    "string-ascii",
+    "string-utf8",
+    "list",
+    "tuple",
+    "optional",
+    "response",
+];
+
+// Write `s` as the body of a string literal, escaping what must be escaped.
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    for ch in s.chars() {
+        match ch {
+            '\\' => f.write_str("\\\\")?,
+            '"' => f.write_str("\\\"")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            '\0' => f.write_str("\\0")?,
+            _ => write!(f, "{ch}")?,
+        }
+    }
+    Ok(())
+}
+
+/// The source text of a token, which lexes back to the same token. Tokens
+/// with no text of their own (`Eof`, `Error` and `Placeholder`) are empty.
+/// Whitespace is a single space, and indentation a single line break. A
+/// comment is written with a `;;` prefix.
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Eof | Token::Error(_) | Token::Placeholder => Ok(()),
+            Token::Whitespace => f.write_str(" "),
+            Token::Indentation | Token::Newline => f.write_str("\n"),
+            Token::Lparen => f.write_str("("),
+            Token::Rparen => f.write_str(")"),
+            Token::Lbrace => f.write_str("{"),
+            Token::Rbrace => f.write_str("}"),
+            Token::Colon => f.write_str(":"),
+            Token::Comma => f.write_str(","),
+            Token::Dot => f.write_str("."),
+            Token::DotDot => f.write_str(".."),
+            Token::Ellipsis => f.write_str("..."),
+            Token::Int(n) => write!(f, "{n}"),
+            Token::Uint(n) => write!(f, "u{n}"),
+            #[cfg(feature = "bignum")]
+            Token::BigInt(n) => write!(f, "{n}"),
+            #[cfg(feature = "bignum")]
+            Token::BigUint(n) => write!(f, "u{n}"),
+            Token::AsciiString(s) => {
+                f.write_str("\"")?;
+                write_escaped(f, s)?;
+                f.write_str("\"")
+            }
+            Token::Utf8String(s) => {
+                f.write_str("u\"")?;
+                write_escaped(f, s)?;
+                f.write_str("\"")
+            }
+            Token::Bytes(bytes) => {
+                f.write_str("0x")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+            Token::Char(ch) => match ch {
+                '\n' => f.write_str("#\\newline"),
+                ' ' => f.write_str("#\\space"),
+                '\t' => f.write_str("#\\tab"),
+                '\r' => f.write_str("#\\\\r"),
+                '\0' => f.write_str("#\\\\0"),
+                _ => write!(f, "#\\{ch}"),
+            },
+            Token::Ident(name) | Token::QualifiedName(name) => f.write_str(name),
+            Token::Plus => f.write_str("+"),
+            Token::Minus => f.write_str("-"),
+            Token::Multiply => f.write_str("*"),
+            Token::Divide => f.write_str("/"),
+            Token::Percent => f.write_str("%"),
+            Token::Less => f.write_str("<"),
+            Token::LessEqual => f.write_str("<="),
+            Token::Greater => f.write_str(">"),
+            Token::GreaterEqual => f.write_str(">="),
+            Token::Equal => f.write_str("="),
+            Token::NotEqual => f.write_str("!="),
+            Token::Arrow => f.write_str("->"),
+            Token::DoubleColon => f.write_str("::"),
+            Token::Bang => f.write_str("!"),
+            Token::Question => f.write_str("?"),
+            Token::Quote => f.write_str("'"),
+            Token::Hash => f.write_str("#"),
+            Token::At => f.write_str("@"),
+            Token::Attribute(name) => write!(f, "#[{name}]"),
+            Token::Annotation(name) => write!(f, "@{name}"),
+            Token::Comment(text) if text.is_empty() => f.write_str(";;"),
+            Token::Comment(text) => write!(f, ";; {text}"),
+        }
+    }
+}
+
+/// An optional classification of a token, beyond its kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenCategory {
+    /// An identifier naming a built-in variable.
+    Builtin,
+    /// An identifier naming a type.
+    Type,
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::Eof => TokenKind::Eof,
+            Token::Whitespace => TokenKind::Whitespace,
+            Token::Indentation => TokenKind::Indentation,
+            Token::Newline => TokenKind::Newline,
+            Token::Lparen => TokenKind::Lparen,
+            Token::Rparen => TokenKind::Rparen,
+            Token::Lbrace => TokenKind::Lbrace,
+            Token::Rbrace => TokenKind::Rbrace,
+            Token::Colon => TokenKind::Colon,
+            Token::Comma => TokenKind::Comma,
+            Token::Dot => TokenKind::Dot,
+            Token::DotDot => TokenKind::DotDot,
+            Token::Ellipsis => TokenKind::Ellipsis,
+            Token::Int(_) => TokenKind::Int,
+            Token::Uint(_) => TokenKind::Uint,
+            #[cfg(feature = "bignum")]
+            Token::BigInt(_) => TokenKind::BigInt,
+            #[cfg(feature = "bignum")]
+            Token::BigUint(_) => TokenKind::BigUint,
+            Token::AsciiString(_) => TokenKind::AsciiString,
+            Token::Utf8String(_) => TokenKind::Utf8String,
+            Token::Bytes(_) => TokenKind::Bytes,
+            Token::Char(_) => TokenKind::Char,
+            Token::Ident(_) => TokenKind::Ident,
+            Token::QualifiedName(_) => TokenKind::QualifiedName,
+            Token::Plus => TokenKind::Plus,
+            Token::Minus => TokenKind::Minus,
+            Token::Multiply => TokenKind::Multiply,
+            Token::Divide => TokenKind::Divide,
+            Token::Percent => TokenKind::Percent,
+            Token::Less => TokenKind::Less,
+            Token::LessEqual => TokenKind::LessEqual,
+            Token::Greater => TokenKind::Greater,
+            Token::GreaterEqual => TokenKind::GreaterEqual,
+            Token::Equal => TokenKind::Equal,
+            Token::NotEqual => TokenKind::NotEqual,
+            Token::Arrow => TokenKind::Arrow,
+            Token::DoubleColon => TokenKind::DoubleColon,
+            Token::Bang => TokenKind::Bang,
+            Token::Question => TokenKind::Question,
+            Token::Quote => TokenKind::Quote,
+            Token::Hash => TokenKind::Hash,
+            Token::At => TokenKind::At,
+            Token::Attribute(_) => TokenKind::Attribute,
+            Token::Annotation(_) => TokenKind::Annotation,
+            Token::Comment(_) => TokenKind::Comment,
+            Token::Error(_) => TokenKind::Error,
+            Token::Placeholder => TokenKind::Placeholder,
+        }
+    }
+
+    /// Can this token begin an expression? Literals, identifiers and opening
+    /// delimiters can, as can `+` and `-`, which may prefix an integer
+    /// literal. Binary-only operators, closing delimiters and separators
+    /// cannot.
+    pub fn can_start_expr(&self) -> bool {
+        #[cfg(feature = "bignum")]
+        if matches!(self, Token::BigInt(_) | Token::BigUint(_)) {
+            return true;
+        }
+        matches!(
+            self,
+            Token::Int(_)
+                | Token::Uint(_)
+                | Token::AsciiString(_)
+                | Token::Utf8String(_)
+                | Token::Bytes(_)
+                | Token::Char(_)
+                | Token::Ident(_)
+                | Token::QualifiedName(_)
+                | Token::Lparen
+                | Token::Lbrace
+                | Token::Plus
+                | Token::Minus
+        )
+    }
+
+    /// A case-folded key for an identifier, for case-insensitive lookups.
+    /// The token itself keeps its original spelling. `-` and `_` are not
+    /// folded together, since they are distinct characters in names
+    /// regardless of case. Returns `None` if this is not an identifier.
+    pub fn normalized(&self) -> Option<String> {
+        match self {
+            Token::Ident(name) => Some(name.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+
+    /// A rough cost of this token, for estimating the complexity of an
+    /// input. Strings, identifiers and buffers weigh their length in bytes
+    /// (but at least 1), whitespace, comments and `Eof` weigh nothing, and
+    /// every other token weighs 1.
+    pub fn weight(&self) -> u64 {
+        match self {
+            Token::Eof
+            | Token::Whitespace
+            | Token::Indentation
+            | Token::Newline
+            | Token::Comment(_) => 0,
+            Token::AsciiString(s)
+            | Token::Utf8String(s)
+            | Token::Ident(s)
+            | Token::QualifiedName(s) => s.len().max(1) as u64,
+            Token::Bytes(bytes) => bytes.len().max(1) as u64,
+            _ => 1,
+        }
+    }
+
+    /// The length in bytes of the UTF-8 encoding of a string token's decoded
+    /// value, or `None` if this is not a string token.
+    pub fn utf8_byte_len(&self) -> Option<usize> {
+        match self {
+            Token::AsciiString(s) | Token::Utf8String(s) => Some(s.len()),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes in a buffer token, or `None` if this is not a
+    /// buffer.
+    pub fn byte_len(&self) -> Option<usize> {
+        match self {
+            Token::Bytes(bytes) => Some(bytes.len()),
+            _ => None,
+        }
+    }
+
+    /// The number of unicode scalar values in a string token's decoded
+    /// value, or `None` if this is not a string token.
+    pub fn char_len(&self) -> Option<usize> {
+        match self {
+            Token::AsciiString(s) | Token::Utf8String(s) => Some(s.chars().count()),
+            _ => None,
+        }
+    }
+}
+
+/// A range of the input. Lines and columns start at 1, and both the start
+/// and the end are inclusive, so a single character token starts and ends at
+/// the same position. The only zero-width token, `Eof`, is placed just past
+/// the end of the input, with its end equal to its start.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Span {
     pub start_line: u32,
     pub start_column: u32,
@@ -38,7 +394,414 @@ pub struct Span {
     pub end_column: u32,
 }
 
+/// A position in the input, with the line and column numbered from 1 as in
+/// a `Span`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Identifies the file a span comes from, when lexing multiple files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
+/// A span along with the file it is in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileSpan {
+    pub file_id: FileId,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct PlacedToken {
     pub span: Span,
     pub token: Token,
 }
+
+impl Span {
+    /// The range of bytes in `source` covered by this span. Positions past
+    /// the end of `source` (e.g. for `Eof`) are clamped to its length.
+    ///
+    /// Columns are counted in `char`s, as with the default
+    /// `ColumnMode::Codepoints`; a span counted in grapheme clusters does not
+    /// map to the right bytes.
+    pub fn byte_range(&self, source: &str) -> Range<usize> {
+        let mut start = source.len();
+        let mut end = source.len();
+        let mut line = 1;
+        let mut column = 1;
+        for (i, ch) in source.char_indices() {
+            if (line, column) == (self.start_line, self.start_column) {
+                start = i;
+            }
+            if (line, column) == (self.end_line, self.end_column) {
+                end = i + ch.len_utf8();
+                break;
+            }
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        start..end.max(start)
+    }
+
+    /// The span starting at the start of this span and ending at the end of
+    /// `last`.
+    pub fn cover(&self, last: &Span) -> Span {
+        Span {
+            start_line: self.start_line,
+            start_column: self.start_column,
+            end_line: last.end_line,
+            end_column: last.end_column,
+        }
+    }
+
+    /// This span, ending no later than the last character of `line` in
+    /// `source` (not including the line ending). A span which ends before
+    /// `line` is unchanged. This keeps a multi-line span, such as one from
+    /// error recovery, to a single line for display.
+    pub fn clamp_to_line(&self, line: u32, source: &str) -> Span {
+        if self.end_line <= line {
+            return self.clone();
+        }
+        let text = source.lines().nth(line.saturating_sub(1) as usize);
+        let len = text.map_or(0, |text| text.chars().count()) as u32;
+        let mut clamped = Span {
+            start_line: self.start_line,
+            start_column: self.start_column,
+            end_line: line,
+            end_column: len,
+        };
+        // Don't end before the start, e.g. when the span starts at the line
+        //  ending
+        if (clamped.end_line, clamped.end_column) < (self.start_line, self.start_column) {
+            clamped.end_line = self.start_line;
+            clamped.end_column = self.start_column;
+        }
+        clamped
+    }
+
+    pub fn in_file(self, file_id: FileId) -> FileSpan {
+        FileSpan {
+            file_id,
+            span: self,
+        }
+    }
+}
+
+impl PlacedToken {
+    /// Are the tokens equal, wherever they are in the input?
+    pub fn token_eq(&self, other: &PlacedToken) -> bool {
+        self.token == other.token
+    }
+}
+
+/// The sum of the weights of `tokens`.
+pub fn total_weight(tokens: &[PlacedToken]) -> u64 {
+    tokens.iter().map(|placed| placed.token.weight()).sum()
+}
+
+/// Are the token streams equal, ignoring the spans of their tokens?
+pub fn token_seq_eq(a: &[PlacedToken], b: &[PlacedToken]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.token_eq(b))
+}
+
+/// The span covering `tokens`, from the start of the first to the end of the
+/// last. Whitespace and comments at either edge are not included, so that
+/// the span of a node does not depend on the trivia around it. Returns `None`
+/// if there are no tokens other than trivia.
+pub fn span_of(tokens: &[PlacedToken]) -> Option<Span> {
+    let is_trivia = |placed: &&PlacedToken| {
+        matches!(
+            placed.token,
+            Token::Whitespace | Token::Indentation | Token::Newline | Token::Comment(_)
+        )
+    };
+    let first = tokens.iter().find(|placed| !is_trivia(placed))?;
+    let last = tokens.iter().rev().find(|placed| !is_trivia(placed))?;
+    Some(first.span.cover(&last.span))
+}
+
+#[cfg(feature = "source-text")]
+impl PlacedToken {
+    /// The exact text of this token in `source`, the input it was lexed from.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.span.byte_range(source)]
+    }
+
+    /// For a string literal, its decoded value along with its raw text in
+    /// `source`, with the quotes, any `u` prefix and escapes intact. `None`
+    /// if this is not a string.
+    pub fn string_forms<'s, 'a>(&'s self, source: &'a str) -> Option<(&'s str, &'a str)> {
+        match &self.token {
+            Token::AsciiString(value) | Token::Utf8String(value) => {
+                Some((value, self.text(source)))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_kind() {
+        assert_eq!(Token::Int(5).kind(), Token::Int(9).kind());
+        assert_eq!(Token::Int(5).kind(), TokenKind::Int);
+        assert_ne!(Token::Int(5).kind(), Token::Ident("x".to_string()).kind());
+        assert_eq!(Token::Ident("x".to_string()).kind(), TokenKind::Ident);
+        assert_eq!(Token::Lparen.kind(), TokenKind::Lparen);
+    }
+
+    #[test]
+    fn start_of_expression() {
+        assert!(Token::Lparen.can_start_expr());
+        assert!(Token::Lbrace.can_start_expr());
+        assert!(Token::Int(1).can_start_expr());
+        assert!(Token::Ident("a".to_string()).can_start_expr());
+        assert!(Token::Minus.can_start_expr());
+
+        assert!(!Token::Rparen.can_start_expr());
+        assert!(!Token::Comma.can_start_expr());
+        assert!(!Token::Colon.can_start_expr());
+        assert!(!Token::Multiply.can_start_expr());
+        assert!(!Token::Eof.can_start_expr());
+    }
+
+    #[test]
+    fn normalize_identifiers() {
+        let upper = Token::Ident("Foo".to_string());
+        let lower = Token::Ident("foo".to_string());
+        assert_eq!(upper.normalized(), lower.normalized());
+        assert_eq!(upper.normalized(), Some("foo".to_string()));
+        assert_ne!(upper, lower);
+
+        assert_ne!(
+            Token::Ident("my-VAR".to_string()).normalized(),
+            Token::Ident("my_var".to_string()).normalized()
+        );
+        assert_eq!(Token::AsciiString("Foo".to_string()).normalized(), None);
+    }
+
+    #[test]
+    fn token_weights() {
+        use crate::lexer::{tokenize, Lexer};
+
+        let short = Token::AsciiString("hi".to_string());
+        let long = Token::AsciiString("hello, world".to_string());
+        assert!(long.weight() > short.weight());
+        assert_eq!(Token::AsciiString(String::new()).weight(), 1);
+        assert_eq!(Token::Bytes(vec![1, 2, 3]).weight(), 3);
+
+        let (tokens, _) = tokenize("(){}:,");
+        assert!(tokens[..6].iter().all(|placed| placed.token.weight() == 1));
+        assert_eq!(total_weight(&tokens), 6);
+
+        let (tokens, _) = tokenize("(foo \"bar\") ;; free");
+        assert_eq!(total_weight(&tokens), 1 + 3 + 3 + 1);
+
+        // Significant newlines weigh nothing, like the whitespace they replace
+        let input = "(foo\n  bar)\n";
+        let mut lexer = Lexer::builder().significant_newlines(true).build(input);
+        let tokens = lexer.read_tokens(8);
+        assert!(tokens.iter().any(|placed| placed.token == Token::Newline));
+        assert_eq!(total_weight(&tokens), total_weight(&tokenize(input).0));
+    }
+
+    #[test]
+    fn string_lengths() {
+        let ascii = Token::AsciiString("hello".to_string());
+        assert_eq!(ascii.utf8_byte_len(), Some(5));
+        assert_eq!(ascii.char_len(), Some(5));
+
+        let multi_byte = Token::Utf8String("héllo".to_string());
+        assert_eq!(multi_byte.utf8_byte_len(), Some(6));
+        assert_eq!(multi_byte.char_len(), Some(5));
+
+        let emoji = Token::Utf8String("👍!".to_string());
+        assert_eq!(emoji.utf8_byte_len(), Some(5));
+        assert_eq!(emoji.char_len(), Some(2));
+
+        assert_eq!(Token::Ident("hello".to_string()).char_len(), None);
+        assert_eq!(Token::Int(5).utf8_byte_len(), None);
+    }
+
+    #[test]
+    fn buffer_length() {
+        assert_eq!(Token::Bytes(vec![0xde, 0xad, 0xbe]).byte_len(), Some(3));
+        assert_eq!(Token::Bytes(vec![]).byte_len(), Some(0));
+        assert_eq!(Token::AsciiString("abc".to_string()).byte_len(), None);
+    }
+
+    #[test]
+    fn span_byte_range() {
+        let source = "(é\n  foo)";
+        let span = Span {
+            start_line: 2,
+            start_column: 3,
+            end_line: 2,
+            end_column: 5,
+        };
+        assert_eq!(span.byte_range(source), 6..9);
+
+        let span = Span {
+            start_line: 1,
+            start_column: 2,
+            end_line: 2,
+            end_column: 1,
+        };
+        assert_eq!(&source[span.byte_range(source)], "é\n ");
+
+        // Past the end of the input
+        let span = Span {
+            start_line: 2,
+            start_column: 7,
+            end_line: 2,
+            end_column: 7,
+        };
+        assert_eq!(span.byte_range(source), 10..10);
+    }
+
+    #[test]
+    fn byte_range_of_each_token() {
+        use crate::lexer::tokenize;
+
+        // Multi-byte characters and combining marks are a column each
+        let source = "(foo u\"e\u{301}é\" u\"🇫🇷\"\n  u\"👍\") x";
+        let (tokens, errors) = tokenize(source);
+        assert!(errors.is_empty());
+        let text: String = tokens
+            .iter()
+            .map(|placed| &source[placed.span.byte_range(source)])
+            .collect();
+        assert_eq!(text, source);
+    }
+
+    #[test]
+    fn compare_tokens_without_spans() {
+        use crate::lexer::tokenize;
+
+        let (a, _) = tokenize("  (foo 1)");
+        let (b, _) = tokenize("\n\n (foo   1)");
+        assert!(a[1].token_eq(&b[1]));
+        assert_ne!(a[1], b[1]);
+        assert!(token_seq_eq(&a, &b));
+
+        let (c, _) = tokenize("  (foo 2)");
+        assert!(!token_seq_eq(&a, &c));
+        assert!(!token_seq_eq(&a, &a[1..]));
+    }
+
+    #[test]
+    fn clamp_span_to_line() {
+        let source = "(foo bar\r\n  baz)\n";
+        let span = Span {
+            start_line: 1,
+            start_column: 6,
+            end_line: 2,
+            end_column: 5,
+        };
+        assert_eq!(
+            span.clamp_to_line(1, source),
+            Span {
+                start_line: 1,
+                start_column: 6,
+                end_line: 1,
+                end_column: 8,
+            }
+        );
+        assert_eq!(span.clamp_to_line(2, source), span);
+
+        // Starting at the line ending
+        let span = Span {
+            start_line: 1,
+            start_column: 9,
+            end_line: 2,
+            end_column: 2,
+        };
+        assert_eq!(
+            span.clamp_to_line(1, source),
+            Span {
+                start_line: 1,
+                start_column: 9,
+                end_line: 1,
+                end_column: 9,
+            }
+        );
+    }
+
+    #[test]
+    fn span_of_tokens() {
+        use crate::lexer::{tokenize, Lexer};
+
+        let (tokens, _) = tokenize("  (foo\n  bar) ;; done\n");
+        assert_eq!(
+            span_of(&tokens[..tokens.len() - 1]),
+            Some(Span {
+                start_line: 1,
+                start_column: 3,
+                end_line: 2,
+                end_column: 6,
+            })
+        );
+        assert_eq!(span_of(&tokens[..1]), None);
+        assert_eq!(span_of(&[]), None);
+
+        // Newlines at either edge are trivia too
+        let input = "\n(foo\n  bar)\n";
+        let mut lexer = Lexer::builder().significant_newlines(true).build(input);
+        let mut tokens = lexer.read_tokens(9);
+        assert_eq!(tokens.pop().map(|placed| placed.token), Some(Token::Eof));
+        assert_eq!(tokens[0].token, Token::Newline);
+        assert_eq!(tokens[tokens.len() - 1].token, Token::Newline);
+        assert_eq!(
+            span_of(&tokens),
+            Some(Span {
+                start_line: 2,
+                start_column: 1,
+                end_line: 3,
+                end_column: 6,
+            })
+        );
+        assert_eq!(span_of(&tokens[..1]), None);
+    }
+
+    #[cfg(feature = "source-text")]
+    #[test]
+    fn token_text() {
+        use crate::lexer::Lexer;
+
+        let source = "(foo \"a\\\"b\") ;; the end";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.read_tokens(8);
+        assert_eq!(tokens[1].text(source), "foo");
+        assert_eq!(tokens[3].text(source), "\"a\\\"b\"");
+        assert_eq!(tokens[6].text(source), ";; the end");
+        assert_eq!(tokens[7].token, Token::Eof);
+        assert_eq!(tokens[7].text(source), "");
+    }
+
+    #[cfg(feature = "source-text")]
+    #[test]
+    fn string_forms() {
+        use crate::lexer::Lexer;
+
+        let source = "\"a\\nb\" u\"\\u{e9}\" foo";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.read_tokens(5);
+        let (value, raw) = tokens[0].string_forms(source).unwrap();
+        assert_eq!(value, "a\nb");
+        assert_eq!(raw, "\"a\\nb\"");
+        assert_eq!((value.chars().count(), raw.chars().count()), (3, 6));
+        assert_eq!(tokens[2].string_forms(source), Some(("é", "u\"\\u{e9}\"")));
+        assert_eq!(tokens[4].string_forms(source), None);
+    }
+}
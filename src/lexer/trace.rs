@@ -0,0 +1,12 @@
+// A record of the lexer's internal decisions, for debugging the lexer
+//  itself. Only compiled in with the `trace` feature.
+
+use super::token::{Span, TokenKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    /// A character was read, becoming the next character to be lexed.
+    ReadChar { ch: char, line: u32, column: u32 },
+    /// A token was returned from `read_token`.
+    ReadToken { kind: TokenKind, span: Span },
+}
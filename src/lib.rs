@@ -0,0 +1,11 @@
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::assign_op_pattern,
+    clippy::redundant_field_names
+)]
+
+pub mod lexer;
+pub mod parser;
+pub mod source_map;
+#[cfg(feature = "wasm")]
+pub mod wasm;
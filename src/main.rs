@@ -1,5 +1,4 @@
-mod lexer;
-use crate::lexer::token::Token;
+use parser2::lexer;
 use std::io::{self, Read};
 
 fn main() {
@@ -9,20 +8,5 @@ fn main() {
         println!("Error reading from stdin: {}", e);
         return;
     }
-    let mut lex = lexer::Lexer::new(input.as_str());
-    loop {
-        let token = lex.read_token();
-        println!(
-            "{}:{}..{}:{}: {:?}",
-            token.span.start_line,
-            token.span.start_column,
-            token.span.end_line,
-            token.span.end_column,
-            token.token
-        );
-        match token.token {
-            Token::Eof => break,
-            _ => (),
-        }
-    }
+    print!("{}", lexer::debug_dump(input.as_str()));
 }
@@ -0,0 +1,12 @@
+use crate::lexer::error::LexerError;
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// An error reported by the lexer
+    Lexer(LexerError),
+    UnexpectedClosingParen,
+    UnexpectedClosingBrace,
+    MissingClosingDelimiter,
+    ExpectedColon,
+    ExpectedValue,
+}
@@ -0,0 +1,638 @@
+pub mod error;
+
+use crate::lexer::error::LexerError;
+use crate::lexer::token::{PlacedToken, Span, Token, TokenKind};
+use crate::lexer::Lexer;
+use error::ParseError;
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Atom(Token),
+    List(Vec<PlacedExpr>),
+    Tuple(Vec<TupleEntry>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TupleEntry {
+    pub key: PlacedExpr,
+    // The span of the `:` between the key and value, if it was there
+    pub colon: Option<Span>,
+    pub value: PlacedExpr,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PlacedExpr {
+    pub span: Span,
+    pub expr: Expr,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PlacedError {
+    pub span: Span,
+    pub error: ParseError,
+}
+
+pub struct Parser<'a> {
+    input: &'a str,
+    lexer: Lexer<'a>,
+    // How many of the lexer's errors have been added to `errors`
+    lexer_errors: usize,
+    next: PlacedToken,
+    // Like the lexer, collect errors and continue on (when possible)
+    errors: Vec<PlacedError>,
+    // The kind and span of each open `(` or `{` enclosing the current position
+    open: Vec<(TokenKind, Span)>,
+}
+
+// Is `second` immediately after `first`, with nothing in between?
+fn adjacent(first: &Span, second: &Span) -> bool {
+    first.end_line == second.start_line && first.end_column + 1 == second.start_column
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        let mut lexer = Lexer::new(input);
+        let next = lexer.read_token();
+        let mut parser = Self {
+            input,
+            lexer,
+            lexer_errors: 0,
+            next,
+            errors: vec![],
+            open: vec![],
+        };
+        parser.forward_lexer_errors();
+        parser
+    }
+
+    pub fn errors(&self) -> &[PlacedError] {
+        &self.errors
+    }
+
+    pub fn parse(&mut self) -> Vec<PlacedExpr> {
+        let mut exprs = vec![];
+        loop {
+            self.skip_trivia();
+            if self.next.token == Token::Eof {
+                return exprs;
+            }
+            if let Some(expr) = self.parse_expr() {
+                exprs.push(expr);
+            }
+        }
+    }
+
+    fn advance(&mut self) -> PlacedToken {
+        let next = self.lexer.read_token();
+        self.forward_lexer_errors();
+        std::mem::replace(&mut self.next, next)
+    }
+
+    // Add the errors the lexer has reported since the last call to `errors`.
+    fn forward_lexer_errors(&mut self) {
+        for placed in &self.lexer.errors()[self.lexer_errors..] {
+            if let Token::Error(error) = &placed.token {
+                self.errors.push(PlacedError {
+                    span: placed.span.clone(),
+                    error: ParseError::Lexer(error.clone()),
+                });
+            }
+        }
+        self.lexer_errors = self.lexer.errors().len();
+    }
+
+    // Negate an integer literal at `span`, if it can be negated. The
+    //  magnitude of `i128::MIN` does not fit in an `i128`, so the lexer
+    //  reports it as an overflow; once negated it fits, and the error is
+    //  dropped.
+    fn negate(&mut self, token: &Token, span: &Span) -> Option<Token> {
+        match token {
+            Token::Int(i128::MAX) if self.is_min_magnitude(span) => {
+                let overflow = ParseError::Lexer(LexerError::IntegerOverflow);
+                self.errors
+                    .retain(|e| !(e.span == *span && e.error == overflow));
+                Some(Token::Int(i128::MIN))
+            }
+            Token::Int(n) => n.checked_neg().map(Token::Int),
+            _ => None,
+        }
+    }
+
+    // Is the integer literal at `span` the magnitude of `i128::MIN`?
+    fn is_min_magnitude(&self, span: &Span) -> bool {
+        self.input[span.byte_range(self.input)].parse::<u128>() == Ok(i128::MIN.unsigned_abs())
+    }
+
+    // Apply a `+` or `-` sign to the integer literal `int`, if it is one.
+    fn apply_sign(&mut self, sign: &Token, int: &PlacedToken) -> Option<Token> {
+        match (sign, &int.token) {
+            (Token::Plus, Token::Int(n)) => Some(Token::Int(*n)),
+            (Token::Minus, token) => self.negate(token, &int.span),
+            _ => None,
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Token::Whitespace | Token::Comment(_) = self.next.token {
+            self.advance();
+        }
+    }
+
+    fn parse_expr(&mut self) -> Option<PlacedExpr> {
+        let placed = self.advance();
+        match placed.token {
+            Token::Lparen => Some(self.parse_list(placed.span)),
+            Token::Lbrace => Some(self.parse_tuple(placed.span)),
+            Token::Rparen => {
+                self.errors.push(PlacedError {
+                    span: placed.span,
+                    error: ParseError::UnexpectedClosingParen,
+                });
+                None
+            }
+            Token::Rbrace => {
+                self.errors.push(PlacedError {
+                    span: placed.span,
+                    error: ParseError::UnexpectedClosingBrace,
+                });
+                None
+            }
+            // A sign directly attached to an integer literal (`-5`, `+5`) is
+            //  part of the literal, not a reference to the `-` or `+` function.
+            sign @ (Token::Minus | Token::Plus) if adjacent(&placed.span, &self.next.span) => {
+                let int = self.next.clone();
+                match self.apply_sign(&sign, &int) {
+                    Some(token) => {
+                        let int = self.advance();
+                        Some(PlacedExpr {
+                            span: placed.span.cover(&int.span),
+                            expr: Expr::Atom(token),
+                        })
+                    }
+                    None => Some(PlacedExpr {
+                        span: placed.span,
+                        expr: Expr::Atom(sign),
+                    }),
+                }
+            }
+            token => Some(PlacedExpr {
+                span: placed.span,
+                expr: Expr::Atom(token),
+            }),
+        }
+    }
+
+    fn parse_list(&mut self, open: Span) -> PlacedExpr {
+        self.open.push((TokenKind::Lparen, open.clone()));
+        let mut items = vec![];
+        loop {
+            self.skip_trivia();
+            if self.at_end(TokenKind::Rparen) {
+                break;
+            }
+            if let Some(expr) = self.parse_expr() {
+                items.push(expr);
+            }
+        }
+        let last = items.last().map(|item| &item.span);
+        let close = self.close(TokenKind::Rparen, &open, last);
+        self.fold_negation(items, open.cover(&close))
+    }
+
+    // A unary minus applied to an integer literal, `(- 5)`, is folded into
+    //  the negative constant. Any other list, such as `(- 10 3)`, is left as
+    //  a call.
+    fn fold_negation(&mut self, items: Vec<PlacedExpr>, span: Span) -> PlacedExpr {
+        if let [PlacedExpr {
+            expr: Expr::Atom(Token::Minus),
+            ..
+        }, PlacedExpr {
+            expr: Expr::Atom(operand),
+            span: operand_span,
+        }] = items.as_slice()
+        {
+            if let Some(token) = self.negate(operand, operand_span) {
+                return PlacedExpr {
+                    span,
+                    expr: Expr::Atom(token),
+                };
+            }
+        }
+        PlacedExpr {
+            span,
+            expr: Expr::List(items),
+        }
+    }
+
+    fn parse_tuple(&mut self, open: Span) -> PlacedExpr {
+        self.open.push((TokenKind::Lbrace, open.clone()));
+        let mut entries = vec![];
+        loop {
+            self.skip_trivia();
+            if self.at_end(TokenKind::Rbrace) {
+                break;
+            }
+            if self.next.token == Token::Comma {
+                self.advance();
+                continue;
+            }
+            let key = match self.parse_expr() {
+                Some(key) => key,
+                None => continue,
+            };
+
+            self.skip_trivia();
+            let colon = if self.next.token == Token::Colon {
+                let colon = self.advance().span;
+                self.skip_trivia();
+                Some(colon)
+            } else {
+                self.errors.push(PlacedError {
+                    span: self.next.span.clone(),
+                    error: ParseError::ExpectedColon,
+                });
+                None
+            };
+
+            if self.at_end(TokenKind::Rbrace) || self.next.token == Token::Comma {
+                self.errors.push(PlacedError {
+                    span: self.next.span.clone(),
+                    error: ParseError::ExpectedValue,
+                });
+                continue;
+            }
+            if let Some(value) = self.parse_expr() {
+                entries.push(TupleEntry { key, colon, value });
+            }
+        }
+        let last = entries.last().map(|entry: &TupleEntry| &entry.value.span);
+        let close = self.close(TokenKind::Rbrace, &open, last);
+        PlacedExpr {
+            span: open.cover(&close),
+            expr: Expr::Tuple(entries),
+        }
+    }
+
+    // Has the list or tuple being parsed ended? It ends at its closing
+    //  delimiter, at the end of the input, or at a closing delimiter that
+    //  matches an enclosing opener (meaning that this one was never closed).
+    fn at_end(&self, closer: TokenKind) -> bool {
+        let opener = match self.next.token {
+            Token::Eof => return true,
+            Token::Rparen => TokenKind::Lparen,
+            Token::Rbrace => TokenKind::Lbrace,
+            _ => return false,
+        };
+        self.next.token.kind() == closer || self.open.iter().any(|(kind, _)| *kind == opener)
+    }
+
+    // Finish the list or tuple opened at `open`, consuming its closing
+    //  delimiter and returning its span. If the delimiter is missing, report
+    //  it and return the span of the last item (`last`) instead, so that the
+    //  recovered node still covers its contents.
+    fn close(&mut self, closer: TokenKind, open: &Span, last: Option<&Span>) -> Span {
+        self.open.pop();
+        if self.next.token.kind() == closer {
+            return self.advance().span;
+        }
+        self.errors.push(PlacedError {
+            span: open.clone(),
+            error: ParseError::MissingClosingDelimiter,
+        });
+        last.unwrap_or(open).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(token: Token, span: Span) -> PlacedExpr {
+        PlacedExpr {
+            span,
+            expr: Expr::Atom(token),
+        }
+    }
+
+    fn span(start_column: u32, end_column: u32) -> Span {
+        Span {
+            start_line: 1,
+            start_column,
+            end_line: 1,
+            end_column,
+        }
+    }
+
+    #[test]
+    fn fold_unary_minus() {
+        let mut parser = Parser::new("-5");
+        assert_eq!(parser.parse(), vec![atom(Token::Int(-5), span(1, 2))]);
+
+        parser = Parser::new("(- 5)");
+        assert_eq!(parser.parse(), vec![atom(Token::Int(-5), span(1, 5))]);
+
+        parser = Parser::new("(- -5)");
+        assert_eq!(parser.parse(), vec![atom(Token::Int(5), span(1, 6))]);
+
+        parser = Parser::new("(- 10 3)");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 8),
+                expr: Expr::List(vec![
+                    atom(Token::Minus, span(2, 2)),
+                    atom(Token::Int(10), span(4, 5)),
+                    atom(Token::Int(3), span(7, 7)),
+                ]),
+            }]
+        );
+
+        // Only integer literals are folded
+        parser = Parser::new("(- a)");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 5),
+                expr: Expr::List(vec![
+                    atom(Token::Minus, span(2, 2)),
+                    atom(Token::Ident("a".to_string()), span(4, 4)),
+                ]),
+            }]
+        );
+
+        // A detached minus is not folded outside of a list
+        parser = Parser::new("- 5");
+        assert_eq!(
+            parser.parse(),
+            vec![
+                atom(Token::Minus, span(1, 1)),
+                atom(Token::Int(5), span(3, 3)),
+            ]
+        );
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn fold_i128_min() {
+        let mut parser = Parser::new("-170141183460469231731687303715884105728");
+        assert_eq!(
+            parser.parse(),
+            vec![atom(Token::Int(i128::MIN), span(1, 40))]
+        );
+        assert!(parser.errors().is_empty());
+
+        parser = Parser::new("(- 170141183460469231731687303715884105728)");
+        assert_eq!(
+            parser.parse(),
+            vec![atom(Token::Int(i128::MIN), span(1, 43))]
+        );
+        assert!(parser.errors().is_empty());
+
+        // Without the sign, the magnitude is still too large, as is anything
+        //  larger with the sign
+        for input in [
+            "170141183460469231731687303715884105728",
+            "-1701411834604692317316873037158841057280",
+        ] {
+            let mut parser = Parser::new(input);
+            parser.parse();
+            assert_eq!(
+                parser.errors()[0].error,
+                ParseError::Lexer(LexerError::IntegerOverflow),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn forward_lexer_errors() {
+        let mut parser = Parser::new("(a ~)");
+        parser.parse();
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(4, 4),
+                error: ParseError::Lexer(LexerError::UnknownSymbol('~')),
+            }]
+        );
+    }
+
+    #[test]
+    fn fold_unary_plus() {
+        let mut parser = Parser::new("+5");
+        assert_eq!(parser.parse(), vec![atom(Token::Int(5), span(1, 2))]);
+
+        parser = Parser::new("+ 5");
+        assert_eq!(
+            parser.parse(),
+            vec![
+                atom(Token::Plus, span(1, 1)),
+                atom(Token::Int(5), span(3, 3)),
+            ]
+        );
+
+        // A run of signs is lexed as one malformed number
+        parser = Parser::new("++5");
+        assert_eq!(parser.parse(), vec![atom(Token::Placeholder, span(1, 3))]);
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(1, 3),
+                error: ParseError::Lexer(LexerError::MalformedNumber),
+            }]
+        );
+
+        parser = Parser::new("(a --5)");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 7),
+                expr: Expr::List(vec![
+                    atom(Token::Ident("a".to_string()), span(2, 2)),
+                    atom(Token::Placeholder, span(4, 6)),
+                ]),
+            }]
+        );
+        assert_eq!(
+            parser.errors()[0].error,
+            ParseError::Lexer(LexerError::MalformedNumber)
+        );
+
+        parser = Parser::new("(+ +1 -2)");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 9),
+                expr: Expr::List(vec![
+                    atom(Token::Plus, span(2, 2)),
+                    atom(Token::Int(1), span(4, 5)),
+                    atom(Token::Int(-2), span(7, 8)),
+                ]),
+            }]
+        );
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn recover_missing_delimiters() {
+        let mut parser = Parser::new("(+ 1 2");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 6),
+                expr: Expr::List(vec![
+                    atom(Token::Plus, span(2, 2)),
+                    atom(Token::Int(1), span(4, 4)),
+                    atom(Token::Int(2), span(6, 6)),
+                ]),
+            }]
+        );
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(1, 1),
+                error: ParseError::MissingClosingDelimiter,
+            }]
+        );
+
+        parser = Parser::new("{ a: 1");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 6),
+                expr: Expr::Tuple(vec![TupleEntry {
+                    key: atom(Token::Ident("a".to_string()), span(3, 3)),
+                    colon: Some(span(4, 4)),
+                    value: atom(Token::Int(1), span(6, 6)),
+                }]),
+            }]
+        );
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(1, 1),
+                error: ParseError::MissingClosingDelimiter,
+            }]
+        );
+
+        // The `)` closes the list, so the tuple inside it is missing its `}`
+        parser = Parser::new("(f {a: 1)");
+        assert_eq!(
+            parser.parse(),
+            vec![PlacedExpr {
+                span: span(1, 9),
+                expr: Expr::List(vec![
+                    atom(Token::Ident("f".to_string()), span(2, 2)),
+                    PlacedExpr {
+                        span: span(4, 8),
+                        expr: Expr::Tuple(vec![TupleEntry {
+                            key: atom(Token::Ident("a".to_string()), span(5, 5)),
+                            colon: Some(span(6, 6)),
+                            value: atom(Token::Int(1), span(8, 8)),
+                        }]),
+                    },
+                ]),
+            }]
+        );
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(4, 4),
+                error: ParseError::MissingClosingDelimiter,
+            }]
+        );
+
+        // Each unclosed opener is reported
+        parser = Parser::new("((a");
+        assert_eq!(parser.parse().len(), 1);
+        assert_eq!(
+            parser.errors(),
+            &[
+                PlacedError {
+                    span: span(2, 2),
+                    error: ParseError::MissingClosingDelimiter,
+                },
+                PlacedError {
+                    span: span(1, 1),
+                    error: ParseError::MissingClosingDelimiter,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_tuples() {
+        let mut parser = Parser::new("{a: 1, b: (+ 1 2)}");
+        let exprs = parser.parse();
+        assert!(parser.errors().is_empty());
+        match &exprs[0].expr {
+            Expr::Tuple(entries) => {
+                assert_eq!(entries.len(), 2);
+                assert_eq!(
+                    entries[1].key,
+                    atom(Token::Ident("b".to_string()), span(8, 8))
+                );
+                assert_eq!(entries[1].value.span, span(11, 17));
+            }
+            _ => panic!("expected a tuple"),
+        }
+
+        parser = Parser::new("{ key  :\t value }");
+        let exprs = parser.parse();
+        assert!(parser.errors().is_empty());
+        match &exprs[0].expr {
+            Expr::Tuple(entries) => {
+                assert_eq!(entries[0].key.span, span(3, 5));
+                assert_eq!(entries[0].colon, Some(span(8, 8)));
+                assert_eq!(entries[0].value.span, span(11, 15));
+            }
+            _ => panic!("expected a tuple"),
+        }
+
+        parser = Parser::new("{a 1, b:}");
+        parser.parse();
+        assert_eq!(
+            parser.errors(),
+            &[
+                PlacedError {
+                    span: span(4, 4),
+                    error: ParseError::ExpectedColon,
+                },
+                PlacedError {
+                    span: span(9, 9),
+                    error: ParseError::ExpectedValue,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut parser = Parser::new("a}");
+        assert_eq!(
+            parser.parse(),
+            vec![atom(Token::Ident("a".to_string()), span(1, 1))]
+        );
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(2, 2),
+                error: ParseError::UnexpectedClosingBrace,
+            }]
+        );
+
+        parser = Parser::new("a)");
+        assert_eq!(
+            parser.parse(),
+            vec![atom(Token::Ident("a".to_string()), span(1, 1))]
+        );
+        assert_eq!(
+            parser.errors(),
+            &[PlacedError {
+                span: span(2, 2),
+                error: ParseError::UnexpectedClosingParen,
+            }]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::lexer::token::Span;
+
+/// A small handle for a span stored in a `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(u32);
+
+enum Entry {
+    Original(Span),
+    // A synthesized span (e.g. from desugaring) which points back to the
+    //  span it was generated from.
+    Derived(SpanId),
+}
+
+/// Stores each span once and hands out `SpanId`s referencing them, so that
+/// synthesized code can point back to the original source.
+#[derive(Default)]
+pub struct SourceMap {
+    entries: Vec<Entry>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a span from the original source.
+    pub fn add(&mut self, span: Span) -> SpanId {
+        self.push(Entry::Original(span))
+    }
+
+    /// Register a synthesized span derived from the span `from`.
+    pub fn add_derived(&mut self, from: SpanId) -> SpanId {
+        self.push(Entry::Derived(from))
+    }
+
+    /// The id this span was directly derived from, if it was synthesized.
+    pub fn derived_from(&self, id: SpanId) -> Option<SpanId> {
+        match self.entries[id.0 as usize] {
+            Entry::Original(_) => None,
+            Entry::Derived(from) => Some(from),
+        }
+    }
+
+    /// The span in the original source for `id`, following any chain of
+    /// derived spans back to the original.
+    pub fn original_span(&self, mut id: SpanId) -> Span {
+        loop {
+            match &self.entries[id.0 as usize] {
+                Entry::Original(span) => return span.clone(),
+                Entry::Derived(from) => id = *from,
+            }
+        }
+    }
+
+    fn push(&mut self, entry: Entry) -> SpanId {
+        let id = SpanId(self.entries.len() as u32);
+        self.entries.push(entry);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_spans() {
+        let foo = Span {
+            start_line: 1,
+            start_column: 2,
+            end_line: 1,
+            end_column: 4,
+        };
+        let bar = Span {
+            start_line: 3,
+            start_column: 1,
+            end_line: 4,
+            end_column: 7,
+        };
+
+        let mut map = SourceMap::new();
+        let foo_id = map.add(foo.clone());
+        let bar_id = map.add(bar.clone());
+        let derived = map.add_derived(bar_id);
+        let derived_twice = map.add_derived(derived);
+
+        assert_ne!(foo_id, bar_id);
+        assert_eq!(map.original_span(foo_id), foo);
+        assert_eq!(map.original_span(bar_id), bar);
+        assert_eq!(map.original_span(derived), bar);
+        assert_eq!(map.original_span(derived_twice), bar);
+
+        assert_eq!(map.derived_from(foo_id), None);
+        assert_eq!(map.derived_from(derived), Some(bar_id));
+        assert_eq!(map.derived_from(derived_twice), Some(derived));
+    }
+}
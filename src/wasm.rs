@@ -0,0 +1,71 @@
+//! JavaScript bindings, for using the lexer from a web playground.
+//!
+//! The crate is only built as a `cdylib` when asked for, so other users don't
+//! pay for it:
+//!
+//! ```sh
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! ```
+//!
+//! ```js
+//! import { tokenize_json } from "parser2";
+//!
+//! // tokenize_json(input: string): string
+//! const { tokens, errors } = JSON.parse(tokenize_json("(+ 1 2)"));
+//! // tokens[0] => { span: { start_line: 1, start_column: 1, end_line: 1, end_column: 1 }, token: "Lparen" }
+//! // tokens[3] => { span: { ... }, token: { Int: 1 } }
+//! ```
+
+use crate::lexer::token::PlacedToken;
+use crate::lexer::tokenize;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize)]
+struct Tokenized {
+    tokens: Vec<PlacedToken>,
+    errors: Vec<PlacedToken>,
+}
+
+/// Lex `input`, returning a JSON object with the `tokens` and `errors`.
+/// Problems are always returned as data in `errors`, never thrown.
+#[wasm_bindgen]
+pub fn tokenize_json(input: &str) -> String {
+    let (tokens, errors) = tokenize(input);
+    match serde_json::to_string(&Tokenized { tokens, errors }) {
+        Ok(json) => json,
+        Err(e) => serde_json::json!({
+            "tokens": [],
+            "errors": [],
+            "internal_error": e.to_string(),
+        })
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_to_json() {
+        let json: serde_json::Value = serde_json::from_str(&tokenize_json("(foo ~)")).unwrap();
+        assert_eq!(json["tokens"].as_array().unwrap().len(), 6);
+        assert_eq!(
+            json["tokens"][0],
+            serde_json::json!({
+                "span": { "start_line": 1, "start_column": 1, "end_line": 1, "end_column": 1 },
+                "token": "Lparen",
+            })
+        );
+        assert_eq!(
+            json["tokens"][1]["token"],
+            serde_json::json!({ "Ident": "foo" })
+        );
+        assert_eq!(json["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            json["errors"][0]["token"],
+            serde_json::json!({ "Error": { "UnknownSymbol": "~" } })
+        );
+    }
+}